@@ -1,91 +1,464 @@
 use std::{
+    fs, hint,
+    path::PathBuf,
     sync::{
         atomic::{AtomicBool, AtomicU64, Ordering},
         Arc,
     },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+use anyhow::Context;
 use clap::ArgMatches;
 use indicatif::{ProgressBar, ProgressStyle};
 use num_format::{Locale, ToFormattedString};
-use rayon::ThreadPoolBuilder;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde::Serialize;
+use superr_optimizers::{
+    corpus::CORPUS,
+    cost::InstructionCount,
+    optimizers::{exhaustive::ExhaustiveOptimizer, Optimizer, OptimizerArgs},
+    space::{parse_addresses, InstructionSpace},
+};
 use superr_vm::{
+    batch::{Batch, LANES},
+    compiled::CompiledProgram,
     instruction::Instruction,
     program::Program,
-    vm::{self, MemValue, VM},
+    vm::{MEM_SIZE, VM},
 };
 
+use super::{corpus::solve, optimize::OptimizerSettings};
+
+pub const SUITES: [&str; 3] = ["interpreter", "enumeration", "optimizers"];
+
+/// Longest programs enumerated by the enumeration suite.
+const ENUMERATION_LENGTH: usize = 4;
+
 pub fn execute(matches: &ArgMatches) -> anyhow::Result<()> {
-    let buffer = matches.get_one::<usize>("buffer").unwrap();
+    if matches.get_flag("live") {
+        return live(matches);
+    }
 
-    let thread_pool = ThreadPoolBuilder::new().build().unwrap();
+    let config = BenchConfig {
+        seed: *matches.get_one::<u64>("seed").unwrap(),
+        warmup: *matches.get_one::<usize>("warmup").unwrap(),
+        iterations: *matches.get_one::<usize>("iterations").unwrap(),
+        timeout: Duration::from_secs(*matches.get_one::<u64>("timeout").unwrap()),
+    };
 
-    let counter = Arc::new(AtomicU64::default());
-    let should_stop = Arc::new(AtomicBool::default());
+    let suites = matches.get_many::<String>("suite").unwrap();
+    let optimizers = matches
+        .get_many::<String>("optimizers")
+        .unwrap()
+        .cloned()
+        .collect::<Vec<String>>();
 
-    // set ctrl c handler
-    let should_stop_2 = should_stop.clone();
-    ctrlc::set_handler(move || should_stop_2.store(true, Ordering::Relaxed)).unwrap();
+    let mut results = vec![];
 
-    // create progress thread
-    let counter_2 = counter.clone();
-    let should_stop_3 = should_stop.clone();
+    for suite in suites {
+        eprintln!("Running {} suite", suite);
 
-    thread_pool.spawn(move || {
-        progress_loop(counter_2, should_stop_3);
-    });
+        match suite.as_str() {
+            "interpreter" => results.extend(interpreter_suite(&config)),
+            "enumeration" => results.extend(enumeration_suite(&config)),
+            "optimizers" => results.extend(optimizers_suite(&config, &optimizers)),
 
-    // create benchmark thread
-    let should_stop_4 = should_stop.clone();
+            _ => unreachable!(),
+        }
+    }
 
-    thread_pool.scope(|_| {
-        bench_loop(*buffer, counter, should_stop_4);
-    });
+    let report = match matches.get_one::<String>("format").unwrap().as_str() {
+        "json" => format_json(&config, &results)?,
+        _ => format_text(&results),
+    };
+
+    match matches.get_one::<PathBuf>("output") {
+        Some(path) => fs::write(path, report)
+            .with_context(|| format!("couldn't write report to {}", path.display()))?,
+        None => print!("{}", report),
+    }
 
     Ok(())
 }
 
-pub fn generate_instruction(max_num: MemValue) -> Instruction {
-    let instruction = fastrand::usize(0..=6);
+struct BenchConfig {
+    /// Seed for generating programs, and for the random number generators of the optimizers.
+    seed: u64,
+
+    /// Amount of iterations which are run, but not measured.
+    warmup: usize,
+
+    /// Amount of iterations which are measured.
+    iterations: usize,
+
+    /// How long an optimizer may take to find the optimal program.
+    timeout: Duration,
+}
+
+impl BenchConfig {
+    /// Runs `f` for the warmup iterations, and then returns what it measured for each of the
+    /// others.
+    fn measure(&self, mut f: impl FnMut() -> f64) -> Vec<f64> {
+        for _ in 0..self.warmup {
+            f();
+        }
+
+        (0..self.iterations).map(|_| f()).collect()
+    }
+
+    /// A thread pool whose threads all have their random number generators seeded.
+    fn thread_pool(&self) -> ThreadPool {
+        let seed = self.seed;
+
+        ThreadPoolBuilder::new()
+            .start_handler(move |index| fastrand::seed(seed.wrapping_add(index as u64 + 1)))
+            .build()
+            .unwrap()
+    }
+}
+
+/// Measurements of a single benchmark.
+struct BenchResult {
+    suite: &'static str,
+    name: String,
+    unit: &'static str,
+    samples: Vec<f64>,
+
+    /// For time-to-solution benchmarks, in how many of the iterations the optimal program was
+    /// found. Only those are part of the samples.
+    solved: Option<usize>,
+}
+
+impl BenchResult {
+    fn mean(&self) -> f64 {
+        self.samples.iter().sum::<f64>() / self.samples.len().max(1) as f64
+    }
+
+    fn median(&self) -> f64 {
+        let mut samples = self.samples.clone();
+        samples.sort_by(f64::total_cmp);
+
+        match samples.len() {
+            0 => 0.0,
+            len if len % 2 == 0 => (samples[len / 2 - 1] + samples[len / 2]) / 2.0,
+            len => samples[len / 2],
+        }
+    }
+
+    /// Sample standard deviation.
+    fn stddev(&self) -> f64 {
+        if self.samples.len() < 2 {
+            return 0.0;
+        }
+
+        let mean = self.mean();
+        let variance = self
+            .samples
+            .iter()
+            .map(|sample| (sample - mean).powi(2))
+            .sum::<f64>()
+            / (self.samples.len() - 1) as f64;
+
+        variance.sqrt()
+    }
+
+    fn min(&self) -> f64 {
+        self.samples.iter().copied().fold(f64::NAN, f64::min)
+    }
+
+    fn max(&self) -> f64 {
+        self.samples.iter().copied().fold(f64::NAN, f64::max)
+    }
+}
+
+/// Throughput of running the same random programs with the interpreter, the compiled backend and
+/// batches.
+fn interpreter_suite(config: &BenchConfig) -> Vec<BenchResult> {
+    const PROGRAMS: usize = 256;
+    const PROGRAM_LENGTH: usize = 256;
+    const RUNS: usize = 64;
+
+    fastrand::seed(config.seed);
+
+    let space = InstructionSpace::new(255);
+    let programs = (0..PROGRAMS)
+        .map(|_| {
+            (0..PROGRAM_LENGTH)
+                .map(|_| space.generate())
+                .collect::<Vec<Instruction>>()
+        })
+        .collect::<Vec<_>>();
+
+    let compiled = programs
+        .iter()
+        .map(|program| CompiledProgram::compile(program))
+        .collect::<Vec<_>>();
+
+    let instructions = (PROGRAMS * PROGRAM_LENGTH * RUNS) as f64;
+
+    // every run starts from the state the previous one left behind, so nothing can be skipped
+    let interpreter = config.measure(|| {
+        let mut vm = VM::default();
+        let start = Instant::now();
+
+        for program in &programs {
+            for _ in 0..RUNS {
+                vm.execute(program);
+            }
+        }
+
+        hint::black_box(vm.state);
+
+        instructions / start.elapsed().as_secs_f64()
+    });
 
-    match instruction {
-        0 => {
-            let val = fastrand::u8(0..max_num);
+    let compiled = config.measure(|| {
+        let mut state = [0; MEM_SIZE];
+        let start = Instant::now();
 
-            Instruction::Load(val)
+        for program in &compiled {
+            for _ in 0..RUNS {
+                program.execute(&mut state);
+            }
         }
 
-        1 | 2 | 5 | 6 => {
-            let addr1 = fastrand::usize(0..vm::MEM_SIZE);
-            let addr2 = fastrand::usize(0..vm::MEM_SIZE);
+        hint::black_box(state);
+
+        instructions / start.elapsed().as_secs_f64()
+    });
 
-            match instruction {
-                1 => Instruction::Swap(addr1, addr2),
-                2 => Instruction::XOR(addr1, addr2),
-                5 => Instruction::Add(addr1, addr2),
-                6 => Instruction::Sub(addr1, addr2),
+    let batch = config.measure(|| {
+        let mut batch = Batch::default();
+        let start = Instant::now();
 
-                _ => panic!("SUPER unexpected error occurred"),
+        for program in &programs {
+            for _ in 0..RUNS / LANES {
+                batch.execute(program);
             }
         }
 
-        3 | 4 => {
-            let addr = fastrand::usize(0..vm::MEM_SIZE);
+        hint::black_box(batch);
 
-            match instruction {
-                3 => Instruction::Inc(addr),
-                4 => Instruction::Decr(addr),
+        instructions / start.elapsed().as_secs_f64()
+    });
 
-                _ => panic!("SUPER unexpected error occurred"),
+    [
+        ("interpreter", interpreter),
+        ("compiled", compiled),
+        ("batch", batch),
+    ]
+    .into_iter()
+    .map(|(name, samples)| BenchResult {
+        suite: "interpreter",
+        name: name.to_string(),
+        unit: "instructions/s",
+        samples,
+        solved: None,
+    })
+    .collect()
+}
+
+/// Rate at which the exhaustive optimizer goes through programs of each length.
+fn enumeration_suite(config: &BenchConfig) -> Vec<BenchResult> {
+    let thread_pool = config.thread_pool();
+
+    let space = InstructionSpace::new(3).with_addresses(parse_addresses("0-3").unwrap());
+
+    (1..=ENUMERATION_LENGTH)
+        .map(|length| {
+            let samples = config.measure(|| {
+                // cell 4 can't be written to, so the target is never reached and every program
+                // of the length is tested
+                let mut program = Program {
+                    instructions: vec![Instruction::Inc(4)],
+                };
+                program
+                    .instructions
+                    .extend([Instruction::Swap(4, 4)].repeat(length));
+
+                let args =
+                    OptimizerArgs::new(program, space.clone(), Arc::new(InstructionCount), length)
+                        .with_min_instructions(length);
+
+                let counter = args.counter.clone();
+                let mut optimizer = ExhaustiveOptimizer::new(args);
+
+                let start = Instant::now();
+                thread_pool.scope(|scope| optimizer.start_optimization(scope));
+
+                counter.load(Ordering::Relaxed) as f64 / start.elapsed().as_secs_f64()
+            });
+
+            BenchResult {
+                suite: "enumeration",
+                name: format!("length-{}", length),
+                unit: "programs/s",
+                samples,
+                solved: None,
             }
+        })
+        .collect()
+}
+
+/// How long each optimizer takes to find the optimal program for each of the corpus entries.
+fn optimizers_suite(config: &BenchConfig, optimizers: &[String]) -> Vec<BenchResult> {
+    let thread_pool = config.thread_pool();
+    let settings = OptimizerSettings::default();
+
+    let mut results = vec![];
+
+    for entry in &CORPUS {
+        for optimizer in optimizers {
+            let samples = config.measure(|| {
+                let (program, elapsed) =
+                    solve(&thread_pool, &settings, optimizer, entry, config.timeout);
+
+                // runs which didn't find it are left out of the samples
+                if program.instructions.len() <= entry.optimal_length {
+                    elapsed.as_secs_f64()
+                } else {
+                    f64::NAN
+                }
+            });
+
+            let samples = samples
+                .into_iter()
+                .filter(|sample| !sample.is_nan())
+                .collect::<Vec<f64>>();
+
+            results.push(BenchResult {
+                suite: "optimizers",
+                name: format!("{}/{}", optimizer, entry.name),
+                unit: "s",
+                solved: Some(samples.len()),
+                samples,
+            });
         }
+    }
+
+    results
+}
 
-        _ => panic!("SUPER unexpected error occurred"),
+fn format_text(results: &[BenchResult]) -> String {
+    let mut report = String::new();
+
+    for result in results {
+        let solved = result
+            .solved
+            .map_or(String::new(), |solved| format!(", solved {}", solved));
+
+        report += &format!(
+            "{:<12} {:<24} {:>16} ± {:<14} median {:<14} {} ({} samples{})\n",
+            result.suite,
+            result.name,
+            format_value(result.mean()),
+            format_value(result.stddev()),
+            format_value(result.median()),
+            result.unit,
+            result.samples.len(),
+            solved,
+        );
+    }
+
+    report
+}
+
+fn format_value(value: f64) -> String {
+    if value >= 1000.0 {
+        (value.round() as u64).to_formatted_string(&Locale::en)
+    } else {
+        format!("{:.4}", value)
     }
 }
 
+/// What `bench` prints with `--format json`.
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    seed: u64,
+    warmup: usize,
+    iterations: usize,
+    results: Vec<JsonResult<'a>>,
+}
+
+/// A [`BenchResult`] along with its statistics. The statistics of results without any samples
+/// are NaN, which is written as null.
+#[derive(Serialize)]
+struct JsonResult<'a> {
+    suite: &'a str,
+    name: &'a str,
+    unit: &'a str,
+    mean: f64,
+    median: f64,
+    stddev: f64,
+    min: f64,
+    max: f64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    solved: Option<usize>,
+
+    samples: &'a [f64],
+}
+
+fn format_json(config: &BenchConfig, results: &[BenchResult]) -> anyhow::Result<String> {
+    let report = JsonReport {
+        seed: config.seed,
+        warmup: config.warmup,
+        iterations: config.iterations,
+        results: results
+            .iter()
+            .map(|result| JsonResult {
+                suite: result.suite,
+                name: &result.name,
+                unit: result.unit,
+                mean: result.mean(),
+                median: result.median(),
+                stddev: result.stddev(),
+                min: result.min(),
+                max: result.max(),
+                solved: result.solved,
+                samples: &result.samples,
+            })
+            .collect(),
+    };
+
+    Ok(serde_json::to_string_pretty(&report)? + "\n")
+}
+
+/// Generates and runs random instructions until interrupted, showing the throughput live.
+fn live(matches: &ArgMatches) -> anyhow::Result<()> {
+    let buffer = matches.get_one::<usize>("buffer").unwrap();
+    let runs = matches.get_one::<usize>("runs").unwrap();
+    let compiled = matches.get_one::<String>("backend").unwrap() == "compiled";
+
+    let thread_pool = ThreadPoolBuilder::new().build().unwrap();
+
+    let counter = Arc::new(AtomicU64::default());
+    let should_stop = Arc::new(AtomicBool::default());
+
+    // set ctrl c handler
+    let should_stop_2 = should_stop.clone();
+    ctrlc::set_handler(move || should_stop_2.store(true, Ordering::Relaxed)).unwrap();
+
+    // create progress thread
+    let counter_2 = counter.clone();
+    let should_stop_3 = should_stop.clone();
+
+    thread_pool.spawn(move || {
+        progress_loop(counter_2, should_stop_3);
+    });
+
+    // create benchmark thread
+    let should_stop_4 = should_stop.clone();
+
+    thread_pool.scope(|_| {
+        bench_loop(*buffer, *runs, compiled, counter, should_stop_4);
+    });
+
+    Ok(())
+}
+
 fn progress_loop(counter: Arc<AtomicU64>, should_stop: Arc<AtomicBool>) {
     let mut last_count = counter.load(Ordering::Relaxed);
 
@@ -120,20 +493,37 @@ fn progress_loop(counter: Arc<AtomicU64>, should_stop: Arc<AtomicBool>) {
     }
 }
 
-fn bench_loop(buffer: usize, counter: Arc<AtomicU64>, should_stop: Arc<AtomicBool>) {
+/// Generates `buffer` instructions at a time and runs them `runs` times, either with the
+/// interpreter or by compiling them first. Each run starts from the state the previous one
+/// left behind.
+fn bench_loop(
+    buffer: usize,
+    runs: usize,
+    compiled: bool,
+    counter: Arc<AtomicU64>,
+    should_stop: Arc<AtomicBool>,
+) {
+    let space = InstructionSpace::new(8);
+
     let mut vm = VM::default();
+    let mut instructions = Vec::with_capacity(buffer);
 
     while !should_stop.load(Ordering::Relaxed) {
-        let instructions = (0..buffer)
-            .map(|_| generate_instruction(8))
-            .collect::<Vec<Instruction>>();
-
-        let mut program = Program::new();
+        instructions.clear();
+        instructions.extend((0..buffer).map(|_| space.generate()));
 
-        program.instructions = instructions;
+        if compiled {
+            let program = CompiledProgram::compile(&instructions);
 
-        vm.execute_program(program);
+            for _ in 0..runs {
+                program.execute(&mut vm.state);
+            }
+        } else {
+            for _ in 0..runs {
+                vm.execute(&instructions);
+            }
+        }
 
-        counter.fetch_add(buffer as u64, Ordering::Relaxed);
+        counter.fetch_add((buffer * runs) as u64, Ordering::Relaxed);
     }
 }
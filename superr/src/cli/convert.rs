@@ -0,0 +1,28 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::ArgMatches;
+use clap_stdin::FileOrStdin;
+
+use super::{read_program, write_program};
+
+pub fn execute(matches: &ArgMatches) -> anyhow::Result<()> {
+    let input = matches
+        .get_one::<FileOrStdin>("input")
+        .context("couldn't get input")?
+        .clone();
+
+    let output = matches.get_one::<PathBuf>("output").unwrap();
+
+    let program = read_program(input)?;
+
+    write_program(&program, output)?;
+
+    eprintln!(
+        "Wrote {} instructions to {}",
+        program.instructions.len(),
+        output.display()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,241 @@
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+use clap::ArgMatches;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use superr_optimizers::{
+    corpus::{self, CorpusEntry, CORPUS},
+    cost,
+    optimizers::OptimizerArgs,
+    space::InstructionSpace,
+};
+use superr_vm::{program::Program, vm::VM};
+
+use super::optimize::OptimizerSettings;
+
+pub fn execute(matches: &ArgMatches) -> anyhow::Result<()> {
+    match matches.subcommand() {
+        Some(("list", _)) => list(),
+        Some(("check", matches)) => check(matches),
+
+        _ => unreachable!("this won't happen"),
+    }
+}
+
+fn list() -> anyhow::Result<()> {
+    for entry in &CORPUS {
+        println!(
+            "{:<12} {:>2} -> {} instructions (max imm {}, addresses {})",
+            entry.name,
+            entry.source.lines().count(),
+            entry.optimal_length,
+            entry.max_imm,
+            entry.addresses
+        );
+    }
+
+    Ok(())
+}
+
+/// How well an optimizer did on a corpus entry, from worst to best.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Status {
+    /// The program found doesn't reach the target, or is shorter than the proven optimal
+    /// length. Either way, something is broken.
+    Invalid,
+
+    /// The optimal length wasn't reached within the budget.
+    Suboptimal,
+
+    Optimal,
+}
+
+impl Status {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "invalid" => Some(Status::Invalid),
+            "suboptimal" => Some(Status::Suboptimal),
+            "optimal" => Some(Status::Optimal),
+
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // padded by the caller, so this has to go through `pad` rather than `write_str`
+        f.pad(match self {
+            Status::Invalid => "invalid",
+            Status::Suboptimal => "suboptimal",
+            Status::Optimal => "optimal",
+        })
+    }
+}
+
+/// Runs the optimizer on every corpus entry, and reports which reached the optimal length.
+///
+/// The report is printed on stdout, one entry per line, and can be given back as a
+/// `--baseline` to find the entries which got worse.
+fn check(matches: &ArgMatches) -> anyhow::Result<()> {
+    let optimizer = matches.get_one::<String>("optimizer").unwrap();
+    let budget = Duration::from_secs(*matches.get_one::<u64>("budget").unwrap());
+
+    let entries = match matches.get_many::<String>("entries") {
+        Some(names) => names
+            .map(|name| corpus::by_name(name).unwrap())
+            .collect::<Vec<&CorpusEntry>>(),
+        None => CORPUS.iter().collect(),
+    };
+
+    let baseline = matches
+        .get_one::<PathBuf>("baseline")
+        .map(load_baseline)
+        .transpose()?;
+
+    let thread_pool = ThreadPoolBuilder::new().build().unwrap();
+    let settings = OptimizerSettings::default();
+
+    let mut report = String::new();
+    let mut checked = 0;
+    let mut optimal = 0;
+    let mut regressions = vec![];
+
+    for entry in entries {
+        let (program, elapsed) = solve(&thread_pool, &settings, optimizer, entry, budget);
+
+        let length = program.instructions.len();
+        let target = VM::compute_state(&entry.program());
+
+        let status = if VM::compute_state(&program) != target || length < entry.optimal_length {
+            Status::Invalid
+        } else if length > entry.optimal_length {
+            Status::Suboptimal
+        } else {
+            Status::Optimal
+        };
+
+        let line = format!(
+            "{:<12} {:<10} {:>2}/{:<2} {:.3}s",
+            entry.name,
+            status,
+            length,
+            entry.optimal_length,
+            elapsed.as_secs_f64()
+        );
+
+        println!("{}", line);
+        report += &line;
+        report += "\n";
+
+        checked += 1;
+
+        if status == Status::Optimal {
+            optimal += 1;
+        }
+
+        let previous = baseline
+            .as_ref()
+            .and_then(|baseline| baseline.get(entry.name).copied());
+
+        if status == Status::Invalid || previous.is_some_and(|previous| status < previous) {
+            regressions.push(entry.name);
+        }
+    }
+
+    if let Some(path) = matches.get_one::<PathBuf>("output") {
+        fs::write(path, report)
+            .with_context(|| format!("couldn't write report to {}", path.display()))?;
+    }
+
+    eprintln!();
+    eprintln!(
+        "{} of {} entries reached the optimal length",
+        optimal, checked
+    );
+
+    if !regressions.is_empty() {
+        bail!("regressions in {}", regressions.join(", "));
+    }
+
+    Ok(())
+}
+
+/// Reads the status of each entry from a report written by `corpus check`.
+fn load_baseline(path: &PathBuf) -> anyhow::Result<HashMap<String, Status>> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("couldn't read baseline {}", path.display()))?;
+
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut fields = line.split_whitespace();
+
+            let name = fields.next().unwrap();
+            let status = fields
+                .next()
+                .and_then(Status::from_name)
+                .with_context(|| format!("invalid baseline line: {}", line))?;
+
+            Ok((name.to_string(), status))
+        })
+        .collect()
+}
+
+/// Runs the optimizer on a corpus entry until it finds a program of the optimal length, or
+/// until the budget runs out. Returns the best program found and how long it took.
+pub(crate) fn solve(
+    thread_pool: &ThreadPool,
+    settings: &OptimizerSettings,
+    optimizer: &str,
+    entry: &CorpusEntry,
+    budget: Duration,
+) -> (Program, Duration) {
+    let args = optimizer_args(entry.program(), entry.space());
+    let optimal = args.optimal.clone();
+    let should_stop = args.should_stop.clone();
+
+    let mut optimizer = settings.build(optimizer, args);
+
+    let start = Instant::now();
+    let done = AtomicBool::default();
+
+    // some optimizers keep going forever, so they're stopped once they have found the optimal
+    // program or ran out of time
+    thread::scope(|threads| {
+        threads.spawn(|| {
+            while !done.load(Ordering::Relaxed) {
+                if optimal.read().unwrap().instructions.len() <= entry.optimal_length
+                    || start.elapsed() > budget
+                {
+                    should_stop.store(true, Ordering::Relaxed);
+                }
+
+                thread::sleep(Duration::from_millis(1));
+            }
+        });
+
+        thread_pool.scope(|scope| optimizer.start_optimization(scope));
+        done.store(true, Ordering::Relaxed);
+    });
+
+    let elapsed = start.elapsed();
+    let program = optimal.read().unwrap().clone();
+
+    (program, elapsed)
+}
+
+/// Arguments for optimizing the given program, minimizing its length.
+fn optimizer_args(program: Program, space: InstructionSpace) -> OptimizerArgs {
+    let length = program.instructions.len();
+
+    OptimizerArgs::new(program, space, cost::by_name("length").unwrap(), length)
+}
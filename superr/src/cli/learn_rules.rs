@@ -0,0 +1,27 @@
+use std::path::PathBuf;
+
+use clap::ArgMatches;
+use superr_optimizers::peephole::RuleDatabase;
+
+pub fn execute(matches: &ArgMatches) -> anyhow::Result<()> {
+    let max_length = matches.get_one::<usize>("max-len").unwrap();
+    let max_imm = matches.get_one::<u8>("max-imm").unwrap();
+    let output = matches.get_one::<PathBuf>("output").unwrap();
+
+    let mut database = RuleDatabase::learn(*max_length, *max_imm);
+
+    // keep the rules we already had, so that the database can be built up incrementally
+    if output.exists() {
+        let existing = RuleDatabase::load(output)?;
+
+        eprintln!("Merging with {} existing rules", existing.len());
+
+        database.extend(existing);
+    }
+
+    database.save(output)?;
+
+    eprintln!("Saved {} rules to {}", database.len(), output.display());
+
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use std::path::PathBuf;
+
+use clap::ArgMatches;
+use superr_optimizers::checkpoint::{self, Checkpoint};
+
+use super::optimize::cost_model;
+
+pub fn execute(matches: &ArgMatches) -> anyhow::Result<()> {
+    let checkpoints = matches
+        .get_many::<PathBuf>("files")
+        .unwrap()
+        .map(Checkpoint::load)
+        .collect::<anyhow::Result<Vec<Checkpoint>>>()?;
+
+    let cost = cost_model(matches)?;
+    let merged = checkpoint::merge(&checkpoints, cost.as_ref())?;
+
+    let count = checkpoints[0].shard.1;
+
+    eprintln!("Merged {} of {} shards", checkpoints.len(), count);
+
+    if !merged.complete {
+        let missing = (0..count)
+            .filter(|i| {
+                !checkpoints
+                    .iter()
+                    .any(|checkpoint| checkpoint.shard.0 == *i)
+            })
+            .map(|i| i.to_string())
+            .collect::<Vec<String>>();
+
+        eprintln!("Missing shards: {}", missing.join(", "));
+    }
+
+    eprintln!(
+        "Programs shorter than {} instructions have been checked",
+        merged.exhausted_below
+    );
+    eprintln!(
+        "Programs tested: {}",
+        checkpoints
+            .iter()
+            .map(|checkpoint| checkpoint.counter)
+            .sum::<u64>()
+    );
+    eprintln!();
+
+    eprintln!("*** Best Program ***");
+
+    for instruction in &merged.best.instructions {
+        println!("{}", instruction);
+    }
+
+    eprintln!();
+    eprintln!(
+        "Best Program: {} Instructions ({})",
+        merged.best.instructions.len(),
+        if merged.proven_optimal {
+            "proven optimal"
+        } else if !checkpoints[0].space.contains_program(&merged.best) {
+            // no shard found anything, so this is the program the search started from
+            "outside of the instruction space"
+        } else {
+            "best found"
+        }
+    );
+
+    Ok(())
+}
@@ -1,5 +1,47 @@
 pub mod bench;
+pub mod convert;
+pub mod corpus;
 pub mod gen;
 pub mod inspect;
+pub mod learn_rules;
+pub mod merge_results;
 pub mod optimize;
 pub mod run;
+
+use std::{fs, io::Read, path::Path};
+
+use anyhow::Context;
+use clap_stdin::FileOrStdin;
+use superr_vm::program::Program;
+
+/// Reads a program, which may be either assembly or encoded, as in `.sbc` files. The two are
+/// told apart by the header rather than the file name, so encoded programs can be piped in too.
+pub(crate) fn read_program(input: FileOrStdin) -> anyhow::Result<Program> {
+    let mut bytes = vec![];
+
+    input
+        .into_reader()
+        .context("couldn't read input")?
+        .read_to_end(&mut bytes)
+        .context("couldn't read input")?;
+
+    if Program::is_encoded(&bytes) {
+        return Program::decode(&bytes).context("couldn't decode program");
+    }
+
+    let contents = String::from_utf8(bytes).context("input isn't valid UTF-8")?;
+
+    contents.parse().context("couldn't parse program")
+}
+
+/// Writes a program to a file, encoded if it has the `.sbc` extension and as assembly
+/// otherwise.
+pub(crate) fn write_program(program: &Program, path: &Path) -> anyhow::Result<()> {
+    let bytes = if path.extension().is_some_and(|extension| extension == "sbc") {
+        program.encode()
+    } else {
+        format!("{}\n", program).into_bytes()
+    };
+
+    fs::write(path, bytes).with_context(|| format!("couldn't write {}", path.display()))
+}
@@ -1,49 +1,105 @@
 use std::{
     mem,
+    path::PathBuf,
     sync::{
         atomic::{AtomicBool, AtomicU64, Ordering},
-        Arc, RwLock,
+        Arc, Mutex,
     },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use anyhow::Context;
-use clap::ArgMatches;
+use anyhow::{bail, Context};
+use clap::{parser::ValueSource, ArgMatches};
 use clap_stdin::FileOrStdin;
 use indicatif::{ProgressBar, ProgressStyle};
 use num_format::{Locale, ToFormattedString};
 use rayon::ThreadPoolBuilder;
-use superr_optimizers::optimizers::{
-    diffing::DiffingOptimizer, exhaustive::ExhaustiveOptimizer,
-    random_search::RandomSearchOptimizer, Optimizer, OptimizerArgs,
+use superr_optimizers::{
+    checkpoint::Checkpoint,
+    cost::{self, CostModel, OpcodeWeights},
+    distance::Metric,
+    history::SolutionHistory,
+    optimizers::{
+        beam::BeamSearchOptimizer, diffing::DiffingOptimizer, exhaustive::ExhaustiveOptimizer,
+        portfolio::PortfolioOptimizer, random_search::RandomSearchOptimizer,
+        window::WindowOptimizer, Optimizer, OptimizerArgs,
+    },
+    pareto::ParetoArchive,
+    peephole::RuleDatabase,
+    result::OptimizationResult,
+    space::{InstructionSpace, DEFAULT_OPCODES},
 };
 use superr_vm::{
-    instruction::Instruction,
+    analysis::{self, CellSet},
+    instruction::Opcode,
     program::Program,
-    vm::{State, VM},
+    vm::{State, VMConfig, MEM_SIZE, VM},
 };
 
+use super::{read_program, write_program};
+
 pub fn execute(matches: &ArgMatches) -> anyhow::Result<()> {
     let input = matches
         .get_one::<FileOrStdin>("input")
         .context("couldn't get input")?
         .clone();
 
-    let contents = input.contents().context("couldn't read input")?;
+    let resume = matches
+        .get_one::<PathBuf>("resume")
+        .map(Checkpoint::load)
+        .transpose()?;
+
+    // when resuming, the best program we had found is the one we're optimizing
+    let program_in = match &resume {
+        Some(checkpoint) => checkpoint.best.clone(),
+        None => read_program(input)?,
+    };
+
+    let length_in = program_in.instructions.len();
+    let target = VM::compute_state(&program_in);
+
+    if resume
+        .as_ref()
+        .is_some_and(|checkpoint| checkpoint.target != target)
+    {
+        bail!("checkpoint's best program doesn't reach its target");
+    }
+
+    let cost = cost_model(matches)?;
+    let cost_in = cost.cost(&program_in.instructions);
 
-    let mut program_in = Program::new();
+    // the instructions being enumerated depend on the space, so it has to stay the same when
+    // resuming. only the generated programs are restricted to it, so the program we start from
+    // may not be, in which case it's never reported.
+    let space = match &resume {
+        Some(checkpoint) => checkpoint.space.clone(),
+        None => instruction_space(matches, &DEFAULT_OPCODES)?,
+    };
 
-    for line in contents.lines() {
-        if !line.is_empty() {
-            program_in
-                .instructions
-                .push(Instruction::from(line.to_string()))
+    // the checkpoint only proves anything about the cost it was pruned with
+    if let Some(checkpoint) = &resume {
+        if checkpoint.cost != cost.description() {
+            bail!(
+                "checkpoint was made with the {} cost model, not {}",
+                checkpoint.cost,
+                cost.description()
+            );
         }
     }
 
-    let length_in = program_in.instructions.len();
-    let target = VM::compute_state(&program_in);
+    // when optimizing for several objectives, we keep track of the whole pareto front
+    let archive = matches.get_many::<String>("pareto").map(|objectives| {
+        let objectives = objectives
+            .map(|name| cost::by_name(name).unwrap())
+            .collect();
+
+        Arc::new(Mutex::new(ParetoArchive::new(objectives)))
+    });
+
+    let history = matches
+        .get_flag("all-solutions")
+        .then(|| Arc::new(Mutex::new(SolutionHistory::new())));
 
     eprintln!("*** Input Program ***");
     print_program(&program_in);
@@ -53,112 +109,436 @@ pub fn execute(matches: &ArgMatches) -> anyhow::Result<()> {
     print_state(&target);
     eprintln!();
 
-    // run optimizer
-    let program_out = optimize(program_in, matches);
+    if !space.contains_program(&program_in) {
+        eprintln!("The input program uses instructions outside of the instruction space");
+        eprintln!();
+    }
+
+    // run the cheap simplification pass first, so that the optimizers start
+    // off with a shorter upper bound
+    let mut program_simplified = cheaper_simplified(&program_in, cost.as_ref());
+
+    if let Some(rules) = matches.get_one::<PathBuf>("rules") {
+        let database = RuleDatabase::load(rules)?;
+        let rewritten = analysis::simplify(&database.apply(&program_simplified));
+
+        // the rules were only tested on a finite amount of inputs, so double check
+        // that they didn't break anything before using the rewritten program. rules
+        // only make programs shorter, which isn't necessarily cheaper either.
+        if VM::compute_state(&rewritten) != target {
+            eprintln!("Rewrite rules produced an incorrect program, ignoring them");
+        } else if cost.cost(&rewritten.instructions) <= cost.cost(&program_simplified.instructions)
+        {
+            program_simplified = rewritten;
+        }
+    }
+
+    eprintln!(
+        "Simplified Program: {} Instructions",
+        program_simplified.instructions.len()
+    );
+    eprintln!();
+
+    // the optimizers only record what they find, which may be nothing when the program we
+    // start from is already optimal
+    for program in [&program_in, &program_simplified] {
+        if !space.contains_program(program) {
+            continue;
+        }
+
+        if let Some(archive) = &archive {
+            archive.lock().unwrap().insert(program.clone());
+        }
+
+        if let Some(history) = &history {
+            history
+                .lock()
+                .unwrap()
+                .record(program.clone(), cost.cost(&program.instructions));
+        }
+    }
+
+    let json = matches.get_one::<String>("format").unwrap() == "json";
+    let start = Instant::now();
+
+    // run optimizer, and simplify whatever it comes up with
+    let (program_out, proven_optimal, programs_tested) = optimize(
+        program_simplified,
+        space.clone(),
+        cost.clone(),
+        archive.clone(),
+        history.clone(),
+        resume,
+        matches,
+    )?;
+    let program_out = cheaper_simplified(&program_out, cost.as_ref());
+
+    // the optimizers can't prove anything about programs they wouldn't have generated
+    if !space.contains_program(&program_out) {
+        bail!("no program made of instructions inside the instruction space was found");
+    }
     let length_out = program_out.instructions.len();
+    let cost_out = cost.cost(&program_out.instructions);
 
     // print results
     eprintln!();
     eprintln!();
-    eprintln!("*** Output Program ***");
-    print_program_stdout(&program_out);
+
+    // the result replaces everything we'd otherwise print on stdout
+    if json {
+        let result = OptimizationResult {
+            vm: VMConfig::CURRENT,
+            optimizer: matches.get_one::<String>("optimizer").unwrap().clone(),
+            cost_model: cost.name().to_string(),
+            target,
+            input: program_in,
+            input_cost: cost_in,
+            output: program_out.clone(),
+            output_cost: cost_out,
+            proven_optimal,
+            programs_tested,
+            elapsed: start.elapsed().as_secs_f64(),
+        };
+
+        println!("{}", serde_json::to_string_pretty(&result)?);
+    } else {
+        // the history goes to stderr, so that stdout can still be used as the output program
+        if let Some(history) = &history {
+            eprintln!("*** All Solutions ***");
+            print_history(&history.lock().unwrap());
+            eprintln!();
+        }
+
+        if let Some(archive) = &archive {
+            let mut archive = archive.lock().unwrap();
+
+            archive.insert(program_out.clone());
+
+            eprintln!("*** Pareto Front ***");
+            print_pareto_front(&archive);
+        } else {
+            eprintln!("*** Output Program ***");
+            print_program_stdout(&program_out);
+        }
+    }
+
+    if let Some(path) = matches.get_one::<PathBuf>("output") {
+        write_program(&program_out, path)?;
+    }
+
     eprintln!();
 
     eprintln!("Input Program: {} Instructions", length_in);
-    eprintln!("Output Program: {} Instructions", length_out);
+    eprintln!(
+        "Output Program: {} Instructions ({})",
+        length_out,
+        if proven_optimal {
+            "proven optimal"
+        } else {
+            "best found"
+        }
+    );
+
+    if cost.name() != "length" {
+        eprintln!("Input Cost: {} ({})", cost_in, cost.name());
+        eprintln!("Output Cost: {} ({})", cost_out, cost.name());
+    }
 
     Ok(())
 }
 
-fn optimize(program: Program, matches: &ArgMatches) -> Program {
-    // TODO: use min_instructions and min_imm
+/// Simplifies the program, unless that makes it more expensive. The simplification pass only
+/// ever makes programs shorter, which isn't necessarily cheaper under every cost model.
+fn cheaper_simplified(program: &Program, cost: &dyn CostModel) -> Program {
+    let simplified = analysis::simplify(program);
 
-    // get arguments
-    // let min_instructions = matches.get_one::<usize>("min-ins").unwrap();
-    let max_instructions = matches.get_one::<usize>("max-ins").unwrap();
+    if cost.cost(&simplified.instructions) <= cost.cost(&program.instructions) {
+        simplified
+    } else {
+        program.clone()
+    }
+}
+
+pub(crate) fn cost_model(matches: &ArgMatches) -> anyhow::Result<Arc<dyn CostModel>> {
+    if let Some(weights) = matches.get_one::<PathBuf>("weights") {
+        return Ok(Arc::new(OpcodeWeights::load(weights)?));
+    }
 
-    // let min_imm = matches.get_one::<u8>("min-imm").unwrap();
-    let max_imm = matches.get_one::<u8>("max-imm").unwrap();
+    let name = matches.get_one::<String>("cost").unwrap();
+
+    Ok(cost::by_name(name).unwrap())
+}
+
+/// Builds the instruction space from the `--min-imm`, `--max-imm`, `--exclude` and
+/// `--addresses` arguments, starting from the given opcodes.
+pub(crate) fn instruction_space(
+    matches: &ArgMatches,
+    opcodes: &[Opcode],
+) -> anyhow::Result<InstructionSpace> {
+    // LOAD 0 is enumerated unless a lower bound is given explicitly, even though --min-imm
+    // has a default
+    let min_imm = match matches.value_source("min-imm") {
+        Some(ValueSource::CommandLine) => *matches.get_one::<u8>("min-imm").unwrap(),
+        _ => 0,
+    };
+    let max_imm = *matches.get_one::<u8>("max-imm").unwrap();
+
+    if min_imm > max_imm {
+        bail!("--min-imm can't be larger than --max-imm");
+    }
+
+    let mut space = InstructionSpace::new(max_imm)
+        .with_opcodes(opcodes.to_vec())
+        .with_imm(min_imm..=max_imm);
+
+    for name in matches.get_many::<String>("exclude").into_iter().flatten() {
+        space = space.without(Opcode::from_name(name).unwrap());
+    }
+
+    if let Some(addresses) = matches.get_one::<CellSet>("addresses") {
+        space = space.with_addresses(*addresses);
+    }
+
+    if space.is_empty() {
+        bail!("every instruction was excluded");
+    }
+
+    Ok(space)
+}
+
+/// Runs the optimizer. Returns the optimal program, whether it was proven to be optimal and the
+/// amount of programs tested.
+fn optimize(
+    program: Program,
+    space: InstructionSpace,
+    cost: Arc<dyn CostModel>,
+    archive: Option<Arc<Mutex<ParetoArchive>>>,
+    history: Option<Arc<Mutex<SolutionHistory>>>,
+    resume: Option<Checkpoint>,
+    matches: &ArgMatches,
+) -> anyhow::Result<(Program, bool, u64)> {
+    // get arguments
+    let min_instructions = *matches.get_one::<usize>("min-ins").unwrap();
+    let mut max_instructions = *matches.get_one::<usize>("max-ins").unwrap();
 
     let optimizer = matches.get_one::<String>("optimizer").unwrap();
 
-    // run program to get the target memory & get amount of instructinos,
-    // we pass these two to the optimizer.
+    // checkpoints are written to the file we resumed from, unless told otherwise
+    let checkpoint = matches
+        .get_one::<PathBuf>("checkpoint")
+        .or(matches.get_one::<PathBuf>("resume"))
+        .cloned();
+    let checkpoint_interval = *matches.get_one::<u64>("checkpoint-interval").unwrap();
+
+    if checkpoint.is_some() && optimizer != "exhaustive" {
+        bail!("only the exhaustive optimizer supports checkpoints");
+    }
+
+    // the windowed optimizer rewrites the program it's given, rather than generating programs
+    let uses_window = optimizer == "windowed"
+        || (optimizer == "portfolio"
+            && matches
+                .get_many::<String>("portfolio")
+                .unwrap()
+                .any(|name| name == "windowed"));
+
+    if min_instructions > 0 && uses_window {
+        bail!("the windowed optimizer doesn't support --min-ins");
+    }
+
+    let shard = matches.get_one::<(usize, usize)>("shard").copied();
+
+    if shard.is_some() && optimizer != "exhaustive" {
+        bail!("only the exhaustive optimizer can be sharded");
+    }
+
+    // the checkpoint is how a shard reports its results, so it's needed to merge them later
+    if shard.is_some() && checkpoint.is_none() {
+        bail!("sharded searches need a --checkpoint to write their results to");
+    }
+
+    if let (Some(shard), Some(checkpoint)) = (shard, &resume) {
+        if shard != checkpoint.shard {
+            bail!(
+                "checkpoint is for shard {}/{}",
+                checkpoint.shard.0,
+                checkpoint.shard.1
+            );
+        }
+    }
+
+    // the instructions being enumerated depend on this, so it has to stay the same
+    if let Some(checkpoint) = &resume {
+        max_instructions = checkpoint.max_instructions;
+    }
+
+    let settings = OptimizerSettings {
+        window: *matches.get_one::<usize>("window").unwrap(),
+        beam_width: *matches.get_one::<usize>("beam-width").unwrap(),
+        metric: Metric::from_name(matches.get_one::<String>("metric").unwrap()).unwrap(),
+        checkpoint: checkpoint.map(|path| (path, Duration::from_secs(checkpoint_interval))),
+        shard,
+        resume,
+    };
+
+    if settings.beam_width == 0 {
+        bail!("beam width must be at least 1");
+    }
+
+    // run program to get the target memory, which the optimizer has to reach
     let target = VM::compute_state(&program);
-    let length = program.instructions.len();
+
+    // the cells we can't use are never written to, so they have to stay 0
+    if let Some(addr) =
+        (0..MEM_SIZE).find(|&addr| !space.addresses.contains(addr) && target[addr] != 0)
+    {
+        bail!(
+            "the target has a non-zero value in cell {}, which can't be used",
+            addr
+        );
+    }
 
     // create thread pool
     let thread_pool = ThreadPoolBuilder::new().build().unwrap();
 
-    // state sort of
-    let optimal = Arc::new(RwLock::new(program));
-    let counter = Arc::new(AtomicU64::default());
-    let should_stop = Arc::new(AtomicBool::default());
+    // programs which can't be cheaper than the one we already have are ruled out by
+    // `OptimizerArgs::max_length`, which also works for costs other than length
+    let mut optimizer_args = OptimizerArgs::new(program, space, cost, max_instructions)
+        .with_min_instructions(min_instructions);
+
+    if let Some(archive) = archive {
+        optimizer_args = optimizer_args.with_archive(archive);
+    }
+
+    if let Some(history) = history {
+        optimizer_args = optimizer_args.with_history(history);
+    }
+
+    if let Some(checkpoint) = &settings.resume {
+        optimizer_args
+            .counter
+            .store(checkpoint.counter, Ordering::Relaxed);
+    }
 
     // create clones of our state which we'll use in the interface
-    let mut optimal_2 = optimal.clone();
-    let counter_2 = counter.clone();
-    let should_stop_2 = should_stop.clone();
-    let should_stop_3 = should_stop.clone();
+    let mut optimal_2 = optimizer_args.optimal.clone();
+    let proven_optimal = optimizer_args.proven_optimal.clone();
+    let counter_2 = optimizer_args.counter.clone();
+    let counter_3 = optimizer_args.counter.clone();
+    let should_stop_2 = optimizer_args.should_stop.clone();
+    let should_stop_3 = optimizer_args.should_stop.clone();
 
     // ctrl c handler
     ctrlc::set_handler(move || should_stop_2.store(true, Ordering::Relaxed)).unwrap();
 
-    let optimizer_args = OptimizerArgs {
-        max_instructions: *max_instructions,
-        max_num: *max_imm,
+    let mut optimizer = if optimizer == "portfolio" {
+        let members = matches.get_many::<String>("portfolio").unwrap();
+
+        // each member runs on its own share of the threads
+        if members.len() > thread_pool.current_num_threads() {
+            bail!(
+                "a portfolio of {} optimizers needs at least {} threads, but there are only {}",
+                members.len(),
+                members.len(),
+                thread_pool.current_num_threads()
+            );
+        }
 
-        target,
-        length,
+        let mut portfolio = PortfolioOptimizer::new(optimizer_args.clone());
 
-        optimal,
-        counter,
-        should_stop,
+        for name in members {
+            portfolio = portfolio.with_member(settings.build(name, optimizer_args.clone()));
+        }
+
+        Box::new(portfolio)
+    } else {
+        settings.build(optimizer, optimizer_args)
     };
 
-    thread_pool.spawn(move || {
+    // the progress is reported outside of the pool, so that the optimizer can use every one of
+    // its threads
+    thread::spawn(move || {
         progress_loop(counter_2, should_stop_3);
     });
 
-    match optimizer.as_str() {
-        "random" => {
-            let mut optimizer = RandomSearchOptimizer::new(optimizer_args);
-
-            // start threads
-            thread_pool.scope(|scope| {
-                optimizer.start_optimization(&scope);
-            });
-        }
-        "exhaustive" => {
-            // initialize optimizer
-            let mut optimizer = ExhaustiveOptimizer::new(optimizer_args);
-
-            // start threads
-            thread_pool.scope(|scope| {
-                optimizer.start_optimization(&scope);
-            });
-        }
-        "diffing" => {
-            // initialize optimizer
-            let mut optimizer = DiffingOptimizer::new(optimizer_args);
-
-            // start threads
-            thread_pool.scope(|scope| {
-                optimizer.start_optimization(&scope);
-            });
-        }
-
-        _ => unreachable!(),
-    }
+    // start threads
+    thread_pool.scope(|scope| {
+        optimizer.start_optimization(scope);
+    });
 
     // return result
-    match Arc::try_unwrap(mem::take(&mut optimal_2)) {
+    let optimal = match Arc::try_unwrap(mem::take(&mut optimal_2)) {
         Ok(optimal) => optimal.into_inner().unwrap(),
         Err(arc) => {
             // this shouldn't happen, but if it does, we can still
             // read the value, just by cloning
             arc.read().unwrap().clone()
         }
+    };
+
+    Ok((
+        optimal,
+        proven_optimal.load(Ordering::Relaxed),
+        counter_3.load(Ordering::Relaxed),
+    ))
+}
+
+/// Optimizer specific options.
+pub(crate) struct OptimizerSettings {
+    pub window: usize,
+    pub beam_width: usize,
+    pub metric: Metric,
+    pub checkpoint: Option<(PathBuf, Duration)>,
+    pub shard: Option<(usize, usize)>,
+    pub resume: Option<Checkpoint>,
+}
+
+/// The defaults of the command line arguments.
+impl Default for OptimizerSettings {
+    fn default() -> Self {
+        Self {
+            window: 3,
+            beam_width: 64,
+            metric: Metric::default(),
+            checkpoint: None,
+            shard: None,
+            resume: None,
+        }
+    }
+}
+
+impl OptimizerSettings {
+    /// Creates the optimizer with the given name.
+    pub fn build(&self, name: &str, args: OptimizerArgs) -> Box<dyn Optimizer + Send> {
+        match name {
+            "random" => Box::new(RandomSearchOptimizer::new(args)),
+            "exhaustive" => {
+                let mut optimizer = ExhaustiveOptimizer::new(args);
+
+                if let Some((path, interval)) = &self.checkpoint {
+                    optimizer = optimizer.with_checkpoint(path.clone(), *interval);
+                }
+
+                if let Some((index, count)) = self.shard {
+                    optimizer = optimizer.with_shard(index, count);
+                }
+
+                if let Some(checkpoint) = &self.resume {
+                    optimizer = optimizer.resume_from(checkpoint);
+                }
+
+                Box::new(optimizer)
+            }
+            "diffing" => Box::new(DiffingOptimizer::new(args).with_metric(self.metric)),
+            "windowed" => Box::new(WindowOptimizer::new(args).with_window(self.window)),
+            "beam" => Box::new(
+                BeamSearchOptimizer::new(args)
+                    .with_width(self.beam_width)
+                    .with_metric(self.metric),
+            ),
+
+            _ => unreachable!(),
+        }
     }
 }
 
@@ -197,22 +577,67 @@ fn progress_loop(counter: Arc<AtomicU64>, should_stop: Arc<AtomicBool>) {
 }
 
 fn print_program(program: &Program) {
-    if program.instructions.len() > 20 {
-        eprintln!("[Program too long to display]");
-        return;
+    for instruction in program.instructions.iter().take(20) {
+        eprintln!("{}", instruction);
     }
 
-    for instruction in &program.instructions {
-        eprintln!("{}", instruction.to_string());
+    if program.instructions.len() > 20 {
+        eprintln!(
+            "[... {} more instructions]",
+            program.instructions.len() - 20
+        );
     }
 }
 
 fn print_program_stdout(program: &Program) {
     for instruction in &program.instructions {
-        println!("{}", instruction.to_string());
+        println!("{}", instruction);
     }
 }
 
+fn print_pareto_front(archive: &ParetoArchive) {
+    let names = archive
+        .objectives()
+        .iter()
+        .map(|objective| objective.name())
+        .collect::<Vec<&str>>();
+
+    for entry in archive.entries() {
+        let costs = names
+            .iter()
+            .zip(&entry.costs)
+            .map(|(name, cost)| format!("{}={}", name, cost))
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        println!("{} | {}", costs, format_program_inline(&entry.program));
+    }
+}
+
+fn print_history(history: &SolutionHistory) {
+    for solution in history.sorted() {
+        eprintln!(
+            "length={} cost={} time={:.3}s worker={} | {}",
+            solution.length,
+            solution.cost,
+            solution.found_after.as_secs_f64(),
+            solution
+                .worker
+                .map_or("-".to_string(), |worker| worker.to_string()),
+            format_program_inline(&solution.program)
+        );
+    }
+}
+
+fn format_program_inline(program: &Program) -> String {
+    program
+        .instructions
+        .iter()
+        .map(|instruction| instruction.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
 fn print_state(state: &State) {
     eprintln!(
         "[{}]",
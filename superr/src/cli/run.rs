@@ -1,7 +1,23 @@
 use anyhow::Context;
 use clap::ArgMatches;
 use clap_stdin::FileOrStdin;
-use superr_vm::{instruction::Instruction, program::Program, vm::VM};
+use serde::Serialize;
+use superr_vm::{
+    program::Program,
+    vm::{State, VMConfig, VM},
+};
+
+use super::read_program;
+
+/// What `run` prints with `--format json`.
+#[derive(Serialize)]
+struct RunOutput {
+    vm: VMConfig,
+    program: Program,
+
+    /// State after running the program.
+    state: State,
+}
 
 pub fn execute(matches: &ArgMatches) -> anyhow::Result<()> {
     let input = matches
@@ -9,24 +25,24 @@ pub fn execute(matches: &ArgMatches) -> anyhow::Result<()> {
         .context("couldn't get input")?
         .clone();
 
-    let contents = input.contents().context("couldn't read input")?;
-
-    let mut program = Program::new();
-
-    for line in contents.lines() {
-        if !line.is_empty() {
-            program
-                .instructions
-                .push(Instruction::from(line.to_string()))
-        }
-    }
+    let program = read_program(input)?;
 
     // create vm
     let mut vm = VM::default();
 
-    vm.execute_program(program);
+    vm.execute(&program.instructions);
+
+    if matches.get_one::<String>("format").unwrap() == "json" {
+        let output = RunOutput {
+            vm: VMConfig::CURRENT,
+            program,
+            state: vm.state,
+        };
 
-    dbg!(vm);
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        dbg!(vm);
+    }
 
     Ok(())
 }
@@ -1,13 +1,26 @@
 pub mod cli;
 
+use std::path::PathBuf;
+
 use clap::{arg, command, value_parser, ArgAction};
 use clap_stdin::FileOrStdin;
+use superr_optimizers::{
+    checkpoint::parse_shard, corpus::CORPUS, cost::COST_MODELS, distance::METRICS,
+    space::parse_addresses,
+};
 
 const INSTRUCTIONS: [&str; 8] = [
     "load", "swap", "xor", "inc", "decr", "add", "sub", "put", /* "jump" */
 ];
 
-const OPTIMIZERS: [&str; 3] = ["random", "exhaustive", "diffing"];
+const OPTIMIZERS: [&str; 6] = [
+    "random",
+    "exhaustive",
+    "diffing",
+    "windowed",
+    "beam",
+    "portfolio",
+];
 
 fn main() -> anyhow::Result<()> {
     let program_generation_args = vec![
@@ -19,8 +32,9 @@ fn main() -> anyhow::Result<()> {
             .action(ArgAction::Set)
             .value_parser(value_parser!(usize))
             .required(true),
-        arg!(--"min-imm" <val> "Minimum value an intermediate value can take")
+        arg!(--"min-imm" <val> "Minimum value an intermediate value can take (every value from 0 is used unless given)")
             .default_value("1")
+            .hide_default_value(true)
             .action(ArgAction::Set)
             .value_parser(value_parser!(u8)),
         arg!(--"max-imm" <val> "Maximum value an intermediate value can take")
@@ -29,11 +43,16 @@ fn main() -> anyhow::Result<()> {
             .value_parser(value_parser!(u8)),
         arg!(--exclude <instructions> "Instruction to exclude (can be used multiple times)")
             .action(ArgAction::Append)
-            .value_parser(clap::builder::PossibleValuesParser::new(
-                INSTRUCTIONS.clone(),
-            )),
+            .value_parser(clap::builder::PossibleValuesParser::new(INSTRUCTIONS)),
+        arg!(--addresses <cells> "Memory cells instructions may use, such as 0-3,7 (LOAD needs cell 0)")
+            .action(ArgAction::Set)
+            .value_parser(parse_addresses),
     ];
 
+    let format_arg = arg!(--format <format> "How to print the results")
+        .default_value("text")
+        .value_parser(["text", "json"]);
+
     let matches = command!()
         .arg_required_else_help(true)
         .subcommand_required(true)
@@ -45,13 +64,32 @@ fn main() -> anyhow::Result<()> {
                     arg!([input] "Superr program to run")
                         .default_value("-")
                         .value_parser(value_parser!(FileOrStdin<String>)),
-                ),
+                )
+                .arg(format_arg.clone()),
         )
         .subcommand(
             command!("gen")
                 .aliases(["g", "generate", "rand", "random"])
                 .about("Generates a random program")
-                .args(&program_generation_args),
+                .args(&program_generation_args)
+                .arg(
+                    arg!(--output <file> "Write the program to a file rather than stdout (encoded if it ends in .sbc)")
+                        .value_parser(value_parser!(PathBuf)),
+                ),
+        )
+        .subcommand(
+            command!("convert")
+                .about("Converts a program between assembly and the encoded .sbc format")
+                .arg(
+                    arg!([input] "Superr program to convert")
+                        .default_value("-")
+                        .value_parser(value_parser!(FileOrStdin<String>)),
+                )
+                .arg(
+                    arg!(--output <file> "Where to write the program (encoded if it ends in .sbc)")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(true),
+                ),
         )
         .subcommand(
             command!("optimize")
@@ -65,19 +103,233 @@ fn main() -> anyhow::Result<()> {
                 .arg(
                     arg!(--optimizer <optimizer> "Optimizer to use")
                         .action(ArgAction::Set)
-                        .value_parser(clap::builder::PossibleValuesParser::new(OPTIMIZERS.clone()))
+                        .value_parser(clap::builder::PossibleValuesParser::new(OPTIMIZERS))
                         .required(true),
                 )
+                .arg(
+                    arg!(--portfolio <optimizers> "Optimizers to run at the same time (portfolio optimizer)")
+                        .default_value("random,beam,exhaustive")
+                        .action(ArgAction::Set)
+                        .value_delimiter(',')
+                        .value_parser(clap::builder::PossibleValuesParser::new(
+                            &OPTIMIZERS[..OPTIMIZERS.len() - 1],
+                        )),
+                )
+                .arg(
+                    arg!(--cost <model> "What to minimize")
+                        .default_value("length")
+                        .action(ArgAction::Set)
+                        .value_parser(clap::builder::PossibleValuesParser::new(COST_MODELS)),
+                )
+                .arg(
+                    arg!(--weights <file> "Minimize per-instruction weights read from a file")
+                        .action(ArgAction::Set)
+                        .value_parser(value_parser!(PathBuf))
+                        .conflicts_with("cost"),
+                )
+                .arg(
+                    arg!(--pareto <objectives> "Keep every program on the Pareto front of these costs")
+                        .action(ArgAction::Set)
+                        .value_delimiter(',')
+                        .value_parser(clap::builder::PossibleValuesParser::new(COST_MODELS)),
+                )
+                .arg(format_arg)
+                .arg(
+                    arg!(--output <file> "Also write the output program to a file (encoded if it ends in .sbc)")
+                        .action(ArgAction::Set)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(--"all-solutions" "Also print every distinct correct program found, on stderr")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    arg!(--checkpoint <file> "Periodically save the progress of an exhaustive search")
+                        .action(ArgAction::Set)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(--"checkpoint-interval" <seconds> "How often to save checkpoints")
+                        .default_value("60")
+                        .action(ArgAction::Set)
+                        .value_parser(value_parser!(u64)),
+                )
+                .arg(
+                    arg!(--resume <file> "Resume an exhaustive search from a checkpoint (overrides the input, --max-ins and --max-imm)")
+                        .action(ArgAction::Set)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(--shard <shard> "Only search slice i of n (0-based, e.g. 2/8) of an exhaustive search")
+                        .action(ArgAction::Set)
+                        .value_parser(parse_shard),
+                )
+                .arg(
+                    arg!(--window <size> "Amount of instructions per window (windowed optimizer)")
+                        .default_value("3")
+                        .action(ArgAction::Set)
+                        .value_parser(value_parser!(usize)),
+                )
+                .arg(
+                    arg!(--"beam-width" <size> "Amount of partial programs kept at each length (beam optimizer)")
+                        .default_value("64")
+                        .action(ArgAction::Set)
+                        .value_parser(value_parser!(usize)),
+                )
+                .arg(
+                    arg!(--metric <metric> "How to measure the distance to the target (beam and diffing optimizers)")
+                        .default_value("euclidean")
+                        .action(ArgAction::Set)
+                        .value_parser(clap::builder::PossibleValuesParser::new(METRICS)),
+                )
+                .arg(
+                    arg!(--rules <file> "Rewrite rules to apply before optimizing")
+                        .action(ArgAction::Set)
+                        .value_parser(value_parser!(PathBuf)),
+                )
                 .args(&program_generation_args),
         )
+        .subcommand(
+            command!("merge-results")
+                .about("Combines the checkpoints of the shards of an exhaustive search")
+                .arg(
+                    arg!(<files> ... "Checkpoints written by each shard")
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(--cost <model> "What the search minimized")
+                        .default_value("length")
+                        .action(ArgAction::Set)
+                        .value_parser(clap::builder::PossibleValuesParser::new(COST_MODELS)),
+                )
+                .arg(
+                    arg!(--weights <file> "The search minimized per-instruction weights read from a file")
+                        .action(ArgAction::Set)
+                        .value_parser(value_parser!(PathBuf))
+                        .conflicts_with("cost"),
+                ),
+        )
+        .subcommand(
+            command!("learn-rules")
+                .about("Learns peephole rewrite rules by superoptimizing short windows, testing the rules on random states")
+                .arg(
+                    arg!(--"max-len" <val> "Length of the longest window to superoptimize")
+                        .default_value("2")
+                        .value_parser(value_parser!(usize)),
+                )
+                .arg(
+                    arg!(--"max-imm" <val> "Maximum value an intermediate value can take")
+                        .default_value("8")
+                        .value_parser(value_parser!(u8)),
+                )
+                .arg(
+                    arg!(-o --output <file> "Rules file to write to")
+                        .default_value("rules.txt")
+                        .value_parser(value_parser!(PathBuf)),
+                ),
+        )
         .subcommand(
             command!("bench")
                 .aliases(["benchmark"])
-                .about("Benchmarks the Superr VM")
+                .about("Benchmarks the Superr VM and optimizers")
+                .arg(
+                    arg!(--suite <suites> "Benchmark suites to run")
+                        .value_delimiter(',')
+                        .value_parser(cli::bench::SUITES)
+                        .default_value("interpreter,enumeration,optimizers"),
+                )
                 .arg(
-                    arg!(--buffer "Amount of instructions to generate at a time")
+                    arg!(--optimizers <optimizers> "Optimizers to time (optimizers suite)")
+                        .value_delimiter(',')
+                        .value_parser(clap::builder::PossibleValuesParser::new(
+                            &OPTIMIZERS[..OPTIMIZERS.len() - 1],
+                        ))
+                        .default_value("exhaustive,beam,random"),
+                )
+                .arg(
+                    arg!(--seed <val> "Seed for generating programs and for the optimizers")
+                        .value_parser(value_parser!(u64))
+                        .default_value("0"),
+                )
+                .arg(
+                    arg!(--warmup <val> "Amount of iterations to run before measuring")
+                        .value_parser(value_parser!(usize))
+                        .default_value("1"),
+                )
+                .arg(
+                    arg!(--iterations <val> "Amount of iterations to measure")
+                        .value_parser(value_parser!(usize))
+                        .default_value("5"),
+                )
+                .arg(
+                    arg!(--timeout <seconds> "How long an optimizer may take to find the optimal program")
+                        .value_parser(value_parser!(u64))
+                        .default_value("10"),
+                )
+                .arg(
+                    arg!(--format <format> "Format of the report")
+                        .value_parser(["text", "json"])
+                        .default_value("text"),
+                )
+                .arg(
+                    arg!(--output <file> "Write the report to a file rather than stdout")
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(--live "Run random instructions until interrupted, showing the throughput live")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    arg!(--buffer "Amount of instructions to generate at a time (live benchmark)")
                         .value_parser(value_parser!(usize))
                         .default_value("512"),
+                )
+                .arg(
+                    arg!(--backend <backend> "How to run the generated instructions (live benchmark)")
+                        .value_parser(["interpreter", "compiled"])
+                        .default_value("interpreter"),
+                )
+                .arg(
+                    arg!(--runs <val> "Amount of times to run each batch of generated instructions (live benchmark)")
+                        .value_parser(value_parser!(usize))
+                        .default_value("1"),
+                ),
+        )
+        .subcommand(
+            command!("corpus")
+                .about("Programs with known optimal lengths, for checking the optimizers")
+                .subcommand_required(true)
+                .subcommand(command!("list").about("Lists the corpus entries"))
+                .subcommand(
+                    command!("check")
+                        .about("Runs an optimizer on the corpus and reports which entries reached the optimal length")
+                        .arg(
+                            arg!(--optimizer <optimizer> "Optimizer to use")
+                                .value_parser(clap::builder::PossibleValuesParser::new(
+                                    &OPTIMIZERS[..OPTIMIZERS.len() - 1],
+                                ))
+                                .default_value("exhaustive"),
+                        )
+                        .arg(
+                            arg!(--budget <seconds> "How long the optimizer may take on each entry")
+                                .value_parser(value_parser!(u64))
+                                .default_value("10"),
+                        )
+                        .arg(
+                            arg!(--entries <names> "Entries to check (all of them by default)")
+                                .value_delimiter(',')
+                                .value_parser(clap::builder::PossibleValuesParser::new(
+                                    CORPUS.iter().map(|entry| entry.name),
+                                )),
+                        )
+                        .arg(
+                            arg!(--baseline <file> "Report of an earlier check, entries which got worse are regressions")
+                                .value_parser(value_parser!(PathBuf)),
+                        )
+                        .arg(
+                            arg!(--output <file> "Also write the report to a file, for use as a baseline")
+                                .value_parser(value_parser!(PathBuf)),
+                        ),
                 ),
         )
         .subcommand(command!("inspect").about("Launches interactive GUI for Superr"))
@@ -86,8 +338,12 @@ fn main() -> anyhow::Result<()> {
     match matches.subcommand() {
         Some(("run", matches)) => cli::run::execute(matches),
         Some(("gen", matches)) => cli::gen::execute(matches),
+        Some(("convert", matches)) => cli::convert::execute(matches),
         Some(("optimize", matches)) => cli::optimize::execute(matches),
+        Some(("merge-results", matches)) => cli::merge_results::execute(matches),
+        Some(("learn-rules", matches)) => cli::learn_rules::execute(matches),
         Some(("bench", matches)) => cli::bench::execute(matches),
+        Some(("corpus", matches)) => cli::corpus::execute(matches),
         Some(("inspect", matches)) => cli::inspect::execute(matches),
 
         _ => unreachable!("this won't happen"),
@@ -6,7 +6,7 @@ use eframe::egui;
 use egui_code_editor::{CodeEditor, ColorTheme};
 use memory_viewer::MemoryViewer;
 use optimizer_options::OptimizerOptions;
-use superr_vm::{instruction::Instruction, program::Program, vm::VM};
+use superr_vm::{program::Program, vm::VM};
 
 static DEFAULT_PROGRAM: &str = "LOAD 3
 SWAP 0 1
@@ -27,17 +27,10 @@ struct SuperrInspect {
 }
 impl SuperrInspect {
     fn execute_program(&mut self) {
-        let instructions = self
-            .code_buffer
-            .lines()
-            .into_iter()
-            .filter(|line| !line.is_empty())
-            .map(|line| Instruction::from(line.to_string()))
-            .collect::<Vec<Instruction>>();
-
-        let program = Program { instructions };
-
-        self.vm.execute_program(program);
+        // invalid programs aren't run, rather than bringing the whole inspector down
+        if let Ok(program) = self.code_buffer.parse::<Program>() {
+            self.vm.execute_program(program);
+        }
     }
 }
 
@@ -0,0 +1,481 @@
+use std::{collections::HashSet, fs, path::Path};
+
+use anyhow::{anyhow, bail, ensure, Context};
+use superr_vm::{
+    address::MemoryAddress,
+    analysis::CellSet,
+    instruction::Opcode,
+    program::Program,
+    vm::{MemValue, State, MEM_SIZE},
+};
+
+use crate::{
+    cost::CostModel,
+    space::{InstructionSpace, DEFAULT_OPCODES},
+};
+
+/// Snapshot of an exhaustive search, which can be used to resume it later.
+///
+/// Checkpoints are stored as plain text, with one `key value` pair per line:
+///
+/// ```text
+/// target 3 3 3 3 0 0 0 0 0 0 0 0
+/// min_num 0
+/// max_num 8
+/// opcodes LOAD SWAP XOR INC DECR ADD SUB
+/// addresses 0 1 2 3 4 5 6 7 8 9 10 11
+/// max_instructions 4
+/// cost length
+/// shard 0/1
+/// length 3
+/// index 1048576
+/// counter 1363426
+/// best LOAD 3, SWAP 0 1, LOAD 3, SWAP 0 2, LOAD 3, SWAP 0 3, LOAD 3
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// Target state of the search.
+    pub target: State,
+
+    /// Instructions being enumerated.
+    pub space: InstructionSpace,
+
+    /// Length of the longest programs being enumerated.
+    pub max_instructions: usize,
+
+    /// The cost model the search was pruned with, as given by [`CostModel::description`].
+    /// Whether the search proves anything depends on it, so it has to stay the same.
+    pub cost: String,
+
+    /// Which slice of the search space this search covers, as a 0-based index and the
+    /// amount of slices.
+    pub shard: (usize, usize),
+
+    /// Length of the programs being enumerated when the checkpoint was taken.
+    pub length: usize,
+
+    /// Index of the first program of `length` instructions which hasn't been checked yet.
+    pub index: u128,
+
+    /// Amount of programs checked so far.
+    pub counter: u64,
+
+    /// Best program found so far.
+    pub best: Program,
+}
+
+impl Checkpoint {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let contents = fs::read_to_string(path).context("couldn't read checkpoint")?;
+
+        let mut target = None;
+        let mut min_num = 0;
+        let mut max_num = None;
+        let mut opcodes = DEFAULT_OPCODES.to_vec();
+        let mut addresses = CellSet::ALL;
+        let mut max_instructions = None;
+        let mut cost = None;
+        let mut shard = (0, 1);
+        let mut length = None;
+        let mut index = None;
+        let mut counter = None;
+        let mut best = None;
+
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+
+            match key {
+                "target" => {
+                    let values = value
+                        .split_whitespace()
+                        .map(str::parse)
+                        .collect::<Result<Vec<MemValue>, _>>()
+                        .context("invalid target")?;
+
+                    target = Some(
+                        State::try_from(values.as_slice())
+                            .map_err(|_| anyhow!("target must have {} values", MEM_SIZE))?,
+                    );
+                }
+
+                "min_num" => min_num = value.parse().context("invalid min_num")?,
+                "max_num" => max_num = Some(value.parse().context("invalid max_num")?),
+
+                "opcodes" => {
+                    opcodes = value
+                        .split_whitespace()
+                        .map(|name| {
+                            Opcode::from_name(name)
+                                .ok_or_else(|| anyhow!("unknown instruction: {}", name))
+                        })
+                        .collect::<anyhow::Result<Vec<Opcode>>>()?;
+                }
+
+                "addresses" => {
+                    addresses = CellSet::EMPTY;
+
+                    for addr in value.split_whitespace() {
+                        let addr: MemoryAddress = addr.parse().context("invalid addresses")?;
+
+                        ensure!(addr < MEM_SIZE, "invalid addresses");
+
+                        addresses.insert(addr);
+                    }
+                }
+                "max_instructions" => {
+                    max_instructions = Some(value.parse().context("invalid max_instructions")?)
+                }
+                "cost" => cost = Some(value.to_string()),
+                "shard" => shard = parse_shard(value).map_err(|err| anyhow!(err))?,
+                "length" => length = Some(value.parse().context("invalid length")?),
+                "index" => index = Some(value.parse().context("invalid index")?),
+                "counter" => counter = Some(value.parse().context("invalid counter")?),
+
+                "best" => {
+                    best = Some(Program {
+                        instructions: value
+                            .split(',')
+                            .map(str::trim)
+                            .filter(|ins| !ins.is_empty())
+                            .map(str::parse)
+                            .collect::<anyhow::Result<_>>()
+                            .context("invalid best")?,
+                    })
+                }
+
+                _ => bail!("unknown checkpoint key: {}", key),
+            }
+        }
+
+        Ok(Self {
+            target: target.context("checkpoint is missing target")?,
+            space: InstructionSpace::new(0)
+                .with_opcodes(opcodes)
+                .with_imm(min_num..=max_num.context("checkpoint is missing max_num")?)
+                .with_addresses(addresses),
+            max_instructions: max_instructions.context("checkpoint is missing max_instructions")?,
+            cost: cost.context("checkpoint is missing cost")?,
+            shard,
+            length: length.context("checkpoint is missing length")?,
+            index: index.context("checkpoint is missing index")?,
+            counter: counter.context("checkpoint is missing counter")?,
+            best: best.context("checkpoint is missing best")?,
+        })
+    }
+
+    /// Saves the checkpoint. The file is written to a temporary path first and then moved into
+    /// place, so an interrupted save never leaves a corrupted checkpoint behind.
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+
+        let target = self
+            .target
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        let opcodes = self
+            .space
+            .opcodes
+            .iter()
+            .map(|opcode| opcode.name())
+            .collect::<Vec<&str>>()
+            .join(" ");
+
+        let addresses = self
+            .space
+            .addresses
+            .iter()
+            .map(|addr| addr.to_string())
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        let best = self
+            .best
+            .instructions
+            .iter()
+            .map(|ins| ins.to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        let contents = format!(
+            "target {}\nmin_num {}\nmax_num {}\nopcodes {}\naddresses {}\nmax_instructions {}\ncost {}\nshard {}/{}\nlength {}\nindex {}\ncounter {}\nbest {}\n",
+            target,
+            self.space.imm.start(),
+            self.space.imm.end(),
+            opcodes,
+            addresses,
+            self.max_instructions,
+            self.cost,
+            self.shard.0,
+            self.shard.1,
+            self.length,
+            self.index,
+            self.counter,
+            best
+        );
+
+        let temp = path.with_extension("tmp");
+
+        fs::write(&temp, contents).context("couldn't write checkpoint")?;
+        fs::rename(&temp, path).context("couldn't write checkpoint")
+    }
+}
+
+/// Parses a shard given as `i/n`, where `i` is the 0-based index of the shard and `n` the amount
+/// of shards.
+pub fn parse_shard(text: &str) -> Result<(usize, usize), String> {
+    let (index, count) = text
+        .split_once('/')
+        .ok_or_else(|| format!("expected a shard such as 0/4, got {}", text))?;
+
+    let index = index.trim().parse().map_err(|_| "invalid shard index")?;
+    let count = count.trim().parse().map_err(|_| "invalid shard count")?;
+
+    if index >= count {
+        return Err(format!(
+            "shard index must be less than the amount of shards ({})",
+            count
+        ));
+    }
+
+    Ok((index, count))
+}
+
+/// The combined outcome of several shards of the same search.
+#[derive(Debug, Clone)]
+pub struct MergedResult {
+    /// Cheapest program found by any shard.
+    pub best: Program,
+
+    /// Whether every shard of the search is accounted for.
+    pub complete: bool,
+
+    /// Every program shorter than this has been checked by every shard given.
+    pub exhausted_below: usize,
+
+    /// Whether no cheaper program than `best` can exist, among the instructions which were
+    /// enumerated.
+    pub proven_optimal: bool,
+}
+
+/// Combines the checkpoints of shards of the same search, which must have been pruned with
+/// the given cost model.
+pub fn merge(checkpoints: &[Checkpoint], cost: &dyn CostModel) -> anyhow::Result<MergedResult> {
+    let first = checkpoints.first().context("no checkpoints to merge")?;
+
+    let mut shards = HashSet::new();
+
+    for checkpoint in checkpoints {
+        ensure!(
+            checkpoint.target == first.target
+                && checkpoint.space == first.space
+                && checkpoint.max_instructions == first.max_instructions,
+            "checkpoints are from different searches"
+        );
+        ensure!(
+            checkpoint.cost == cost.description(),
+            "shard {}/{} was made with the {} cost model, not {}",
+            checkpoint.shard.0,
+            checkpoint.shard.1,
+            checkpoint.cost,
+            cost.description()
+        );
+        ensure!(
+            checkpoint.shard.1 == first.shard.1,
+            "checkpoints are split into different amounts of shards"
+        );
+        ensure!(
+            shards.insert(checkpoint.shard.0),
+            "shard {}/{} was given more than once",
+            checkpoint.shard.0,
+            checkpoint.shard.1
+        );
+    }
+
+    // the program a search started from may be outside of the space, in which case it was
+    // never checked and any program inside of it is better
+    let best = checkpoints
+        .iter()
+        .map(|checkpoint| &checkpoint.best)
+        .min_by_key(|program| {
+            (
+                !first.space.contains_program(program),
+                cost.cost(&program.instructions),
+            )
+        })
+        .unwrap()
+        .clone();
+
+    let complete = shards.len() == first.shard.1;
+
+    let exhausted_below = checkpoints
+        .iter()
+        .map(|checkpoint| checkpoint.length)
+        .min()
+        .unwrap();
+
+    Ok(MergedResult {
+        proven_optimal: complete
+            && first.space.contains_program(&best)
+            && proves_optimal(&best, exhausted_below, cost),
+        best,
+        complete,
+        exhausted_below,
+    })
+}
+
+/// Whether having checked every program shorter than `exhausted_below` means that no program
+/// cheaper than `best` exists.
+pub fn proves_optimal(best: &Program, exhausted_below: usize, cost: &dyn CostModel) -> bool {
+    let min_cost = cost.min_instruction_cost();
+
+    if min_cost == 0 {
+        return false;
+    }
+
+    let max_length = cost.cost(&best.instructions).saturating_sub(1) / min_cost;
+
+    exhausted_below as u64 > max_length
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use crate::{
+        cost::{CodeSize, InstructionCount},
+        space::parse_addresses,
+    };
+
+    use super::*;
+
+    fn checkpoint() -> Checkpoint {
+        Checkpoint {
+            target: [3, 3, 3, 3, 0, 0, 0, 0, 0, 0, 0, 7],
+            space: InstructionSpace::new(0)
+                .with_opcodes(vec![Opcode::Load, Opcode::Swap, Opcode::Inc])
+                .with_imm(1..=8)
+                .with_addresses(parse_addresses("0-3,11").unwrap()),
+            max_instructions: 9,
+            cost: "weights LOAD=2 SWAP=1".to_string(),
+            shard: (2, 5),
+            length: 7,
+            index: u64::MAX as u128 * 3,
+            counter: 1363426,
+            best: "LOAD 3\nSWAP 0 1\nLOAD 3\nSWAP 0 2\nINC 11"
+                .parse()
+                .unwrap(),
+        }
+    }
+
+    /// Saves the checkpoint to a temporary file and loads it back.
+    fn round_trip(checkpoint: &Checkpoint, name: &str) -> Checkpoint {
+        let path = env::temp_dir().join(format!("superr-{}-{}.txt", name, std::process::id()));
+
+        checkpoint.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        loaded.unwrap()
+    }
+
+    #[test]
+    fn save_load_round_trip() {
+        let checkpoint = checkpoint();
+
+        assert_eq!(round_trip(&checkpoint, "checkpoint"), checkpoint);
+    }
+
+    #[test]
+    fn save_load_round_trip_empty_best() {
+        let checkpoint = Checkpoint {
+            target: [0; MEM_SIZE],
+            best: Program {
+                instructions: vec![],
+            },
+            ..checkpoint()
+        };
+
+        assert_eq!(round_trip(&checkpoint, "checkpoint-empty"), checkpoint);
+    }
+
+    /// The checkpoints of every shard of a finished search, where shard 1 found the best
+    /// program. Every program shorter than it was checked.
+    fn shards() -> Vec<Checkpoint> {
+        (0..3)
+            .map(|index| Checkpoint {
+                cost: "length".to_string(),
+                shard: (index, 3),
+                length: 2,
+                index: 0,
+                best: if index == 1 {
+                    "LOAD 3\nSWAP 0 1".parse().unwrap()
+                } else {
+                    checkpoint().best
+                },
+                ..checkpoint()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merge_proves_optimality_once_every_shard_is_done() {
+        let merged = merge(&shards(), &InstructionCount).unwrap();
+
+        assert!(merged.complete);
+        assert!(merged.proven_optimal);
+        assert_eq!(merged.exhausted_below, 2);
+        assert_eq!(merged.best, "LOAD 3\nSWAP 0 1".parse().unwrap());
+    }
+
+    #[test]
+    fn merge_doesnt_prove_anything_with_missing_shards() {
+        let shards = shards();
+        let merged = merge(&shards[..2], &InstructionCount).unwrap();
+
+        assert!(!merged.complete);
+        assert!(!merged.proven_optimal);
+        assert_eq!(merged.best, "LOAD 3\nSWAP 0 1".parse().unwrap());
+    }
+
+    #[test]
+    fn merge_doesnt_prove_anything_with_unfinished_shards() {
+        let mut shards = shards();
+        shards[2].length = 1;
+
+        let merged = merge(&shards, &InstructionCount).unwrap();
+
+        assert!(merged.complete);
+        assert!(!merged.proven_optimal);
+        assert_eq!(merged.exhausted_below, 1);
+    }
+
+    #[test]
+    fn merge_rejects_mismatched_shards() {
+        let mismatches: [fn(&mut Checkpoint); 5] = [
+            |checkpoint| checkpoint.target[0] = 4,
+            |checkpoint| checkpoint.space = checkpoint.space.clone().without(Opcode::Inc),
+            |checkpoint| checkpoint.max_instructions += 1,
+            |checkpoint| checkpoint.shard = (checkpoint.shard.0, 4),
+            // the same shard twice
+            |checkpoint| checkpoint.shard = (0, 3),
+        ];
+
+        for mismatch in mismatches {
+            let mut shards = shards();
+            mismatch(&mut shards[2]);
+
+            assert!(merge(&shards, &InstructionCount).is_err());
+        }
+
+        // shards made with a different cost model than the one given
+        assert!(merge(&shards(), &CodeSize).is_err());
+        assert!(merge(&[], &InstructionCount).is_err());
+    }
+}
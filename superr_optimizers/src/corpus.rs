@@ -0,0 +1,96 @@
+use superr_vm::{program::Program, vm::MemValue};
+
+use crate::space::{parse_addresses, InstructionSpace};
+
+/// A program along with the length of its optimal program, used for checking that the
+/// optimizers still find what they should, and for benchmarking them.
+///
+/// The optimal lengths were proven by running the exhaustive optimizer on each program, within
+/// its instruction space. That space is kept small, so that they can be proven again quickly.
+#[derive(Debug, Clone, Copy)]
+pub struct CorpusEntry {
+    pub name: &'static str,
+
+    /// Assembly of the program, one instruction per line.
+    pub source: &'static str,
+
+    /// Length of the optimal program.
+    pub optimal_length: usize,
+
+    /// Largest value LOAD may take.
+    pub max_imm: MemValue,
+
+    /// Memory cells instructions may use, in the format of [`parse_addresses`].
+    pub addresses: &'static str,
+}
+
+impl CorpusEntry {
+    pub fn program(&self) -> Program {
+        self.source.parse().unwrap()
+    }
+
+    /// The instruction space the optimal length was proven in.
+    pub fn space(&self) -> InstructionSpace {
+        InstructionSpace::new(self.max_imm).with_addresses(parse_addresses(self.addresses).unwrap())
+    }
+}
+
+pub static CORPUS: [CorpusEntry; 7] = [
+    CorpusEntry {
+        name: "double-load",
+        source: "LOAD 1\nSWAP 0 1\nLOAD 1\nSWAP 0 1",
+        optimal_length: 2,
+        max_imm: 3,
+        addresses: "0-3",
+    },
+    CorpusEntry {
+        name: "spread",
+        source: "LOAD 3\nSWAP 0 1\nLOAD 3\nSWAP 0 2\nINC 1",
+        optimal_length: 4,
+        max_imm: 3,
+        addresses: "0-2",
+    },
+    // the default program of the inspector
+    CorpusEntry {
+        name: "fill",
+        source: "LOAD 3\nSWAP 0 1\nLOAD 3\nSWAP 0 2\nLOAD 3\nSWAP 0 3\nLOAD 3",
+        optimal_length: 4,
+        max_imm: 3,
+        addresses: "0-3",
+    },
+    // examples/5_plus_3.s, without the PUT
+    CorpusEntry {
+        name: "5-plus-3",
+        source: "LOAD 5\nSWAP 0 1\nLOAD 3\nADD 0 1",
+        optimal_length: 3,
+        max_imm: 8,
+        addresses: "0-1",
+    },
+    // examples/xor_swap.s, without the PUTs
+    CorpusEntry {
+        name: "xor-swap",
+        source: "LOAD 10\nSWAP 0 1\nLOAD 20\nXOR 0 1\nXOR 1 0\nXOR 0 1",
+        optimal_length: 3,
+        max_imm: 20,
+        addresses: "0-1",
+    },
+    CorpusEntry {
+        name: "count",
+        source: "INC 0\nINC 0\nINC 0\nINC 0\nINC 0",
+        optimal_length: 1,
+        max_imm: 5,
+        addresses: "0",
+    },
+    CorpusEntry {
+        name: "doubling",
+        source: "LOAD 7\nSWAP 0 1\nLOAD 7\nADD 0 1",
+        optimal_length: 3,
+        max_imm: 7,
+        addresses: "0-1",
+    },
+];
+
+/// Finds the corpus entry with the given name.
+pub fn by_name(name: &str) -> Option<&'static CorpusEntry> {
+    CORPUS.iter().find(|entry| entry.name == name)
+}
@@ -0,0 +1,206 @@
+use std::{fs, path::Path, sync::Arc};
+
+use anyhow::{anyhow, Context};
+use superr_vm::{
+    analysis,
+    instruction::{Instruction, Opcode},
+};
+
+/// Names of the built-in cost models, as accepted by [`by_name`].
+pub const COST_MODELS: [&str; 4] = ["length", "latency", "size", "cells"];
+
+/// Looks up one of the built-in cost models by name.
+pub fn by_name(name: &str) -> Option<Arc<dyn CostModel>> {
+    let model: Arc<dyn CostModel> = match name {
+        "length" => Arc::new(InstructionCount),
+        "latency" => Arc::new(OpcodeWeights::latency()),
+        "size" => Arc::new(CodeSize),
+        "cells" => Arc::new(CellsTouched),
+
+        _ => return None,
+    };
+
+    Some(model)
+}
+
+/// Something the optimizers can minimize.
+pub trait CostModel: Send + Sync {
+    /// Short name of the model, used when printing costs.
+    fn name(&self) -> &str;
+
+    /// Describes the model completely, so that two models with the same description compute
+    /// the same costs. This is what checkpoints record, to tell whether a search is resumed with
+    /// the cost it was started with.
+    fn description(&self) -> String {
+        self.name().to_string()
+    }
+
+    /// Computes the cost of a sequence of instructions.
+    fn cost(&self, instructions: &[Instruction]) -> u64;
+
+    /// The least amount appending any single instruction can add to the cost.
+    ///
+    /// This is what lets the optimizers work out how long a program can get before it can't
+    /// possibly be cheaper than the best one found so far. Models for which adding instructions
+    /// can be free should return 0, meaning that there's no such bound.
+    fn min_instruction_cost(&self) -> u64;
+}
+
+/// The amount of instructions in the program.
+pub struct InstructionCount;
+
+impl CostModel for InstructionCount {
+    fn name(&self) -> &str {
+        "length"
+    }
+
+    fn cost(&self, instructions: &[Instruction]) -> u64 {
+        instructions.len() as u64
+    }
+
+    fn min_instruction_cost(&self) -> u64 {
+        1
+    }
+}
+
+/// The size of the program's instructions in bytes, as encoded by [`Instruction::encode`].
+pub struct CodeSize;
+
+impl CostModel for CodeSize {
+    fn name(&self) -> &str {
+        "size"
+    }
+
+    fn cost(&self, instructions: &[Instruction]) -> u64 {
+        instructions
+            .iter()
+            .map(|ins| ins.encoded_len() as u64)
+            .sum()
+    }
+
+    fn min_instruction_cost(&self) -> u64 {
+        2
+    }
+}
+
+/// The amount of distinct memory cells the program reads from or writes to.
+pub struct CellsTouched;
+
+impl CostModel for CellsTouched {
+    fn name(&self) -> &str {
+        "cells"
+    }
+
+    fn cost(&self, instructions: &[Instruction]) -> u64 {
+        analysis::cells_used(instructions).len() as u64
+    }
+
+    fn min_instruction_cost(&self) -> u64 {
+        0
+    }
+}
+
+/// A fixed cost for each opcode, summed over every instruction in the program.
+pub struct OpcodeWeights {
+    name: String,
+    weights: [u64; Opcode::ALL.len()],
+}
+
+impl OpcodeWeights {
+    /// Creates a model where every opcode has a weight of 1, which is the same as counting
+    /// instructions.
+    pub fn uniform(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            weights: [1; Opcode::ALL.len()],
+        }
+    }
+
+    /// A rough latency table, where instructions doing less work (moving or incrementing
+    /// a value) are cheaper than ones doing more, and I/O is expensive.
+    pub fn latency() -> Self {
+        Self::uniform("latency")
+            .with_weight(Opcode::Load, 1)
+            .with_weight(Opcode::Swap, 2)
+            .with_weight(Opcode::XOR, 3)
+            .with_weight(Opcode::Inc, 1)
+            .with_weight(Opcode::Decr, 1)
+            .with_weight(Opcode::Add, 2)
+            .with_weight(Opcode::Sub, 2)
+            .with_weight(Opcode::Put, 10)
+            .with_weight(Opcode::Jmp, 2)
+    }
+
+    /// Loads weights from a file, with one `OPCODE weight` pair per line (e.g. `XOR 3`).
+    /// Opcodes which aren't listed have a weight of 1. Empty lines and lines starting with `;`
+    /// are ignored.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let contents = fs::read_to_string(path).context("couldn't read weights file")?;
+
+        let mut model = Self::uniform("weights");
+
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+
+            let (name, weight) = line
+                .split_once(char::is_whitespace)
+                .with_context(|| format!("invalid weight: {}", line))?;
+
+            let opcode =
+                Opcode::from_name(name).ok_or_else(|| anyhow!("unknown instruction: {}", name))?;
+
+            let weight = weight
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid weight for {}", name))?;
+
+            model = model.with_weight(opcode, weight);
+        }
+
+        Ok(model)
+    }
+
+    pub fn with_weight(mut self, opcode: Opcode, weight: u64) -> Self {
+        self.weights[opcode as usize] = weight;
+        self
+    }
+
+    pub fn weight(&self, opcode: Opcode) -> u64 {
+        self.weights[opcode as usize]
+    }
+}
+
+impl CostModel for OpcodeWeights {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> String {
+        let weights = Opcode::ALL
+            .into_iter()
+            .map(|opcode| format!("{}={}", opcode.name(), self.weight(opcode)))
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        format!("{} {}", self.name, weights)
+    }
+
+    fn cost(&self, instructions: &[Instruction]) -> u64 {
+        instructions
+            .iter()
+            .map(|ins| self.weight(ins.opcode()))
+            .sum()
+    }
+
+    fn min_instruction_cost(&self) -> u64 {
+        // PUT and JMP are never generated by the optimizers, so they don't count
+        Opcode::ALL
+            .into_iter()
+            .filter(|opcode| !matches!(opcode, Opcode::Put | Opcode::Jmp))
+            .map(|opcode| self.weight(opcode))
+            .min()
+            .unwrap()
+    }
+}
@@ -0,0 +1,66 @@
+use superr_vm::vm::State;
+
+/// Names of the distance metrics, as accepted by [`Metric::from_name`].
+pub const METRICS: [&str; 4] = ["euclidean", "hamming", "bits", "modular"];
+
+/// How far apart two states are, which is what guides the searches that build programs one
+/// instruction at a time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Metric {
+    /// Euclidean distance between the values of the cells.
+    #[default]
+    Euclidean,
+
+    /// Amount of cells which differ.
+    Hamming,
+
+    /// Amount of bits which differ. This suits XOR heavy programs, where values which are far
+    /// apart (such as 0x80 and 0x00) can be a single instruction away.
+    BitHamming,
+
+    /// Sum of how many INC or DECR instructions it would take to turn each cell into the
+    /// other, taking into account that values wrap around (so 255 and 0 are 1 apart).
+    Modular,
+}
+
+impl Metric {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "euclidean" => Some(Self::Euclidean),
+            "hamming" => Some(Self::Hamming),
+            "bits" => Some(Self::BitHamming),
+            "modular" => Some(Self::Modular),
+
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Euclidean => "euclidean",
+            Self::Hamming => "hamming",
+            Self::BitHamming => "bits",
+            Self::Modular => "modular",
+        }
+    }
+
+    /// Computes the distance between two states. Only equal states have a distance of 0.
+    pub fn distance(&self, a: &State, b: &State) -> f32 {
+        let cells = a.iter().zip(b);
+
+        match self {
+            Self::Euclidean => cells
+                .map(|(&a, &b)| (a as f32 - b as f32).powi(2))
+                .sum::<f32>()
+                .sqrt(),
+
+            Self::Hamming => cells.filter(|(a, b)| a != b).count() as f32,
+
+            Self::BitHamming => cells.map(|(&a, &b)| (a ^ b).count_ones()).sum::<u32>() as f32,
+
+            Self::Modular => cells
+                .map(|(&a, &b)| a.wrapping_sub(b).min(b.wrapping_sub(a)) as u32)
+                .sum::<u32>() as f32,
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use superr_vm::{
+    analysis::ConstState,
+    batch::{Batch, LANES},
+    instruction::Instruction,
+    vm::{State, MEM_SIZE},
+};
+
+/// A fixed set of input states, used to check whether two instruction sequences compute the
+/// same function.
+///
+/// Unlike comparing against a target state, which only tells us what a program does when run on
+/// a freshly reset VM, this lets us reason about sequences which may appear anywhere in a
+/// program. Testing is not a proof, but with enough vectors a false positive is very unlikely.
+#[derive(Debug, Clone)]
+pub struct TestVectors {
+    pub states: Vec<State>,
+}
+
+impl TestVectors {
+    /// Creates `count` test vectors, the first two of which are all zeros and all ones, and the
+    /// rest random. The same seed always produces the same vectors.
+    pub fn new(count: usize, seed: u64) -> Self {
+        let mut rng = fastrand::Rng::with_seed(seed);
+
+        let states = (0..count)
+            .map(|i| match i {
+                0 => [0; MEM_SIZE],
+                1 => [u8::MAX; MEM_SIZE],
+
+                _ => {
+                    let mut state = [0; MEM_SIZE];
+                    rng.fill(&mut state);
+
+                    state
+                }
+            })
+            .collect();
+
+        Self { states }
+    }
+
+    /// Like [`TestVectors::new`], but cells whose value is known in `entry` are fixed to it, so
+    /// the vectors only cover the states which can actually occur at some point in a program.
+    ///
+    /// If every cell is known there's only one such state, so only one vector is created.
+    pub fn with_entry(entry: &ConstState, count: usize, seed: u64) -> Self {
+        let count = if entry.iter().all(Option::is_some) {
+            1
+        } else {
+            count
+        };
+
+        let mut vectors = Self::new(count, seed);
+
+        for state in &mut vectors.states {
+            for (cell, known) in state.iter_mut().zip(entry) {
+                if let Some(value) = known {
+                    *cell = *value;
+                }
+            }
+        }
+
+        vectors
+    }
+
+    /// Runs the instructions on every test vector, returning the resulting states.
+    ///
+    /// The vectors are run [`LANES`] at a time, in lockstep.
+    pub fn fingerprint(&self, instructions: &[Instruction]) -> Vec<State> {
+        let mut states = Vec::with_capacity(self.states.len());
+
+        self.fingerprint_into(instructions, &mut states);
+
+        states
+    }
+
+    /// Like [`TestVectors::fingerprint`], but replaces the contents of the given buffer rather
+    /// than allocating a new one.
+    pub fn fingerprint_into(&self, instructions: &[Instruction], states: &mut Vec<State>) {
+        states.clear();
+
+        for chunk in self.states.chunks(LANES) {
+            let mut batch = Batch::from_states(chunk);
+            batch.execute(instructions);
+
+            states.extend((0..chunk.len()).map(|lane| batch.state(lane)));
+        }
+    }
+
+    /// Whether the two instruction sequences produce the same states on every test vector.
+    pub fn equivalent(&self, a: &[Instruction], b: &[Instruction]) -> bool {
+        self.fingerprint(a) == self.fingerprint(b)
+    }
+}
@@ -0,0 +1,88 @@
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+use superr_vm::program::Program;
+
+/// A correct program found during optimization.
+#[derive(Debug, Clone)]
+pub struct Solution {
+    pub program: Program,
+
+    /// Amount of instructions in the program.
+    pub length: usize,
+
+    /// Cost of the program, under the cost model being optimized for.
+    pub cost: u64,
+
+    /// Time since the history was created.
+    pub found_after: Duration,
+
+    /// Index of the rayon worker thread which found the program, if it was found on one.
+    pub worker: Option<usize>,
+}
+
+/// Every distinct correct program found during optimization, not just the best one.
+///
+/// This is useful for picking among several equally cheap alternatives.
+pub struct SolutionHistory {
+    started: Instant,
+    seen: HashSet<Program>,
+    solutions: Vec<Solution>,
+}
+
+impl Default for SolutionHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SolutionHistory {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            seen: HashSet::new(),
+            solutions: vec![],
+        }
+    }
+
+    /// Records a program, unless it has already been recorded. Returns whether it was new.
+    pub fn record(&mut self, program: Program, cost: u64) -> bool {
+        if !self.seen.insert(program.clone()) {
+            return false;
+        }
+
+        self.solutions.push(Solution {
+            length: program.instructions.len(),
+            program,
+            cost,
+            found_after: self.started.elapsed(),
+            worker: rayon::current_thread_index(),
+        });
+
+        true
+    }
+
+    /// The recorded programs, in the order they were found.
+    pub fn solutions(&self) -> &[Solution] {
+        &self.solutions
+    }
+
+    /// The recorded programs, cheapest first, then shortest first.
+    pub fn sorted(&self) -> Vec<&Solution> {
+        let mut solutions = self.solutions.iter().collect::<Vec<_>>();
+
+        solutions.sort_by_key(|solution| (solution.cost, solution.length));
+
+        solutions
+    }
+
+    pub fn len(&self) -> usize {
+        self.solutions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.solutions.is_empty()
+    }
+}
@@ -1,46 +1,29 @@
 use superr_vm::{
     instruction::Instruction,
-    vm::{self, MemValue},
+    vm::{State, VM},
 };
 
+pub mod checkpoint;
+pub mod corpus;
+pub mod cost;
+pub mod distance;
+pub mod equivalence;
+pub mod history;
 pub mod optimizers;
+pub mod pareto;
+pub mod peephole;
+pub mod result;
+pub mod space;
 pub mod vm_pool;
 
-pub fn generate_instruction(max_num: MemValue) -> Instruction {
-    let instruction = fastrand::usize(0..=6);
+/// Computes the state after running a single instruction, starting from the given state.
+pub fn step(state: &State, ins: Instruction) -> State {
+    let mut vm = VM {
+        state: *state,
+        ..Default::default()
+    };
 
-    match instruction {
-        0 => {
-            let val = fastrand::u8(0..max_num);
+    vm.execute_instruction(ins);
 
-            Instruction::Load(val)
-        }
-
-        1 | 2 | 5 | 6 => {
-            let addr1 = fastrand::usize(0..vm::MEM_SIZE);
-            let addr2 = fastrand::usize(0..vm::MEM_SIZE);
-
-            match instruction {
-                1 => Instruction::Swap(addr1, addr2),
-                2 => Instruction::XOR(addr1, addr2),
-                5 => Instruction::Add(addr1, addr2),
-                6 => Instruction::Sub(addr1, addr2),
-
-                _ => panic!("SUPER unexpected error occurred"),
-            }
-        }
-
-        3 | 4 => {
-            let addr = fastrand::usize(0..vm::MEM_SIZE);
-
-            match instruction {
-                3 => Instruction::Inc(addr),
-                4 => Instruction::Decr(addr),
-
-                _ => panic!("SUPER unexpected error occurred"),
-            }
-        }
-
-        _ => panic!("SUPER unexpected error occurred"),
-    }
+    vm.state
 }
@@ -0,0 +1,155 @@
+use std::{collections::HashSet, sync::atomic::Ordering};
+
+use rayon::{
+    iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator},
+    Scope,
+};
+use superr_vm::{
+    instruction::Instruction,
+    program::Program,
+    vm::{State, MEM_SIZE, VM},
+};
+
+use crate::{distance::Metric, step};
+
+use super::{Optimizer, OptimizerArgs};
+
+/// A partial program, along with the state it leaves the memory in.
+struct Node {
+    instructions: Vec<Instruction>,
+    state: State,
+}
+
+/// Builds programs breadth first, keeping only the [`BeamSearchOptimizer::width`] partial
+/// programs whose states are closest to the target at each length.
+///
+/// Every partial program is extended with every possible instruction. Partial programs which
+/// reach a state already reached by a shorter (or equally long) one are dropped, since they
+/// can't lead anywhere new.
+pub struct BeamSearchOptimizer {
+    pub args: OptimizerArgs,
+
+    /// Amount of partial programs kept at each length.
+    pub width: usize,
+
+    /// How the distance of a state to the target is measured.
+    pub metric: Metric,
+}
+
+impl Optimizer for BeamSearchOptimizer {
+    fn new(args: OptimizerArgs) -> Self {
+        Self {
+            args,
+            width: 64,
+            metric: Metric::default(),
+        }
+    }
+
+    fn start_optimization<'a>(&'a mut self, _: &Scope<'a>) {
+        if self.should_stop() {
+            return;
+        }
+
+        // each length depends on the one before it, so the lengths are searched one at a
+        // time; expanding the beam is parallelized instead
+        self.worker_loop();
+    }
+
+    fn current_optimal_length(&self) -> usize {
+        self.args.optimal.read().unwrap().instructions.len()
+    }
+
+    fn should_stop(&self) -> bool {
+        self.args.should_stop.load(Ordering::Relaxed)
+    }
+
+    fn worker_loop(&self) {
+        let instructions = self.args.space.instructions();
+
+        let mut seen = HashSet::from([[0; MEM_SIZE]]);
+        let mut beam = vec![Node {
+            instructions: vec![],
+            state: [0; MEM_SIZE],
+        }];
+
+        let mut length = 1;
+
+        // the bound is recomputed after every length, as it may have changed
+        while length <= self.args.max_length() && !beam.is_empty() && !self.should_stop() {
+            let mut expanded = beam
+                .par_iter()
+                .enumerate()
+                .flat_map_iter(|(parent, node)| {
+                    instructions.iter().map(move |&ins| {
+                        let state = step(&node.state, ins);
+                        let distance = self.metric.distance(&state, &self.args.target);
+
+                        (distance, parent, ins, state)
+                    })
+                })
+                .collect::<Vec<(f32, usize, Instruction, State)>>();
+
+            self.args
+                .counter
+                .fetch_add(expanded.len() as u64, Ordering::Relaxed);
+
+            expanded.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+
+            let mut next = Vec::with_capacity(self.width);
+
+            for (_, parent, ins, state) in expanded {
+                if next.len() == self.width {
+                    break;
+                }
+
+                // the state was tracked one instruction at a time, so we double check with
+                // the VM before handing the program over. this happens before deduplicating,
+                // as programs which are too short are kept in the beam instead, and reaching
+                // the target again once they're long enough has to count.
+                if state == self.args.target && length >= self.args.min_instructions {
+                    let mut instructions = beam[parent].instructions.clone();
+                    instructions.push(ins);
+
+                    let program = Program { instructions };
+
+                    if VM::compute_state(&program) == self.args.target {
+                        self.args.submit(program);
+                    }
+
+                    continue;
+                }
+
+                if !seen.insert(state) {
+                    continue;
+                }
+
+                let mut instructions = beam[parent].instructions.clone();
+                instructions.push(ins);
+
+                next.push(Node {
+                    instructions,
+                    state,
+                });
+            }
+
+            beam = next;
+            length += 1;
+        }
+    }
+}
+
+impl BeamSearchOptimizer {
+    /// Sets the amount of partial programs kept at each length.
+    pub fn with_width(mut self, width: usize) -> Self {
+        assert!(width > 0, "beam width must be at least 1");
+
+        self.width = width;
+        self
+    }
+
+    /// Sets how the distance of a state to the target is measured.
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+}
@@ -1,19 +1,40 @@
-use std::{mem, sync::atomic::Ordering};
+use std::sync::atomic::Ordering;
 
 use rayon::Scope;
-use superr_vm::vm::{State, VM};
+use superr_vm::{
+    instruction::Instruction,
+    program::Program,
+    vm::{State, MEM_SIZE, VM},
+};
 
-use crate::generate_instruction;
+use crate::{distance::Metric, step};
 
 use super::{Optimizer, OptimizerArgs};
 
+/// Amount of the best scoring instructions which are considered at every step. One of them is
+/// picked at random, favouring the better ones, so that every attempt builds a different
+/// program.
+const CANDIDATES: usize = 4;
+
+/// Builds programs from scratch, one instruction at a time, always appending one of the
+/// instructions which bring the state closest to the target.
+///
+/// Each attempt starts from an empty program and stops once the target is reached, or the
+/// program gets too long to be cheaper than the optimal one. Workers keep making attempts
+/// until they're stopped, or until even a single instruction would be too expensive.
 pub struct DiffingOptimizer {
     pub args: OptimizerArgs,
+
+    /// How the distance of a state to the target is measured.
+    pub metric: Metric,
 }
 
 impl Optimizer for DiffingOptimizer {
     fn new(args: OptimizerArgs) -> Self {
-        Self { args }
+        Self {
+            args,
+            metric: Metric::default(),
+        }
     }
 
     fn start_optimization<'a>(&'a mut self, scope: &Scope<'a>) {
@@ -21,8 +42,14 @@ impl Optimizer for DiffingOptimizer {
             return;
         }
 
-        // run the worker threads for computing the shortest possible program
-        for _ in 0..rayon::current_num_threads() - 1 {
+        // every attempt appends at least one instruction, so the empty program is never built
+        if self.args.target == [0; MEM_SIZE] && self.args.min_instructions == 0 {
+            self.args.submit(Program::new());
+        }
+
+        // run the worker threads for computing the shortest possible program, one for each
+        // thread of the pool we were started in
+        for _ in 0..rayon::current_num_threads() {
             scope.spawn(|_| self.worker_loop());
         }
     }
@@ -36,65 +63,72 @@ impl Optimizer for DiffingOptimizer {
     }
 
     fn worker_loop(&self) {
-        let mut vm = VM::default();
-
-        let counter = self.args.counter.clone();
-
-        // start with an empty program
-        //let mut current_program = Program::new();
-        let mut current_score = DiffingOptimizer::score(&vm.state, &self.args.target);
+        let instructions = self.args.space.instructions();
 
         while !self.should_stop() {
-            vm.reset();
+            let max_length = self.args.max_length();
 
-            // TODO: can this be simplified?
-            let mut new_program = self.args.optimal.read().unwrap().clone();
+            // no program we could build would be cheaper than the optimal one
+            if max_length == 0 {
+                break;
+            }
 
-            new_program
-                .instructions
-                .push(generate_instruction(self.args.max_num));
-            vm.execute_program(new_program.clone());
+            // start with an empty program
+            let mut program = Program::new();
+            let mut state = [0; MEM_SIZE];
 
-            let new_score = DiffingOptimizer::score(&vm.state, &self.args.target);
+            while program.instructions.len() < max_length && !self.should_stop() {
+                let ins = self.next_instruction(&instructions, &state);
 
-            if current_score > new_score
-                && new_program.instructions.len() < self.current_optimal_length()
-            {
-                // update optimal program
-                eprintln!(
-                    "Found more optimal program ({} instructions)",
-                    new_program.instructions.len()
-                );
+                state = step(&state, ins);
+                program.instructions.push(ins);
 
+                if state == self.args.target
+                    && program.instructions.len() >= self.args.min_instructions
                 {
-                    let mut lock = self.args.optimal.write().unwrap();
-
-                    let _ = mem::replace(&mut *lock, new_program);
-                }
+                    // the state was tracked one instruction at a time, so run the whole
+                    // program before trusting it
+                    if VM::compute_state(&program) == self.args.target {
+                        self.args.submit(program);
+                    }
 
-                current_score = new_score;
-
-                if new_score == 0.0 {
-                    self.should_stop();
+                    break;
                 }
             }
-
-            // increment the counter
-            counter.fetch_add(1, Ordering::Relaxed);
         }
     }
 }
 
 impl DiffingOptimizer {
-    /// Randomly generates a single instruction based on the [`DiffingOptimizerOptions`].
-    /// Euclidean distance.
-    ///
-    /// Developer Note: Maybe apply penalty based on length?
-    fn score(a: &State, b: &State) -> f32 {
-        a.iter()
-            .zip(b)
-            .map(|(&a, &b)| (a as f32 - b as f32).powi(2))
-            .sum::<f32>()
-            .sqrt()
+    /// Sets how the distance of a state to the target is measured.
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Scores every instruction by how close it brings the given state to the target, and
+    /// picks one of the best ones at random.
+    fn next_instruction(&self, instructions: &[Instruction], state: &State) -> Instruction {
+        let mut scored = instructions
+            .iter()
+            .map(|&ins| {
+                let score = self.metric.distance(&step(state, ins), &self.args.target);
+
+                (score, fastrand::u32(..), ins)
+            })
+            .collect::<Vec<(f32, u32, Instruction)>>();
+
+        self.args
+            .counter
+            .fetch_add(instructions.len() as u64, Ordering::Relaxed);
+
+        // the random number breaks ties between equally scoring instructions
+        scored.sort_unstable_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+
+        // the smaller of two picks is more likely to be one of the best instructions
+        let candidates = CANDIDATES.min(scored.len());
+        let pick = fastrand::usize(0..candidates).min(fastrand::usize(0..candidates));
+
+        scored[pick].2
     }
 }
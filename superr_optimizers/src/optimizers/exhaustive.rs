@@ -1,76 +1,161 @@
-use anyhow::anyhow;
-use itertools::Itertools;
 use rayon::{
-    iter::{ParallelBridge, ParallelIterator},
+    iter::{IntoParallelIterator, ParallelIterator},
     Scope,
 };
-use std::{mem, sync::atomic::Ordering};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
 use superr_vm::{
+    batch::{Batch, ALL_LANES, LANES},
     instruction::Instruction,
     program::Program,
-    vm::{MemValue, MEM_SIZE, VM},
+    vm::{State, MEM_SIZE},
+};
+
+use crate::{
+    checkpoint::{self, Checkpoint},
+    step,
 };
 
 use super::{Optimizer, OptimizerArgs};
 
-const INSTRUCTIONS: [&'static str; 7] = ["LOAD", "SWAP", "XOR", "INC", "DECR", "ADD", "SUB"];
+/// Amount of programs checked between looking at whether a checkpoint is due.
+const CHUNK_SIZE: u64 = 1 << 20;
+
+/// Amount of consecutive programs checked by a thread at a time.
+const BLOCK_SIZE: u64 = 1 << 12;
 
+/// Enumerates every program, shortest first.
+///
+/// The search ends once every program which could be cheaper than the optimal one has been
+/// checked, which usually means right after the first length at which a program was found.
+/// The optimal program is then proven to be optimal, unless the search was sharded.
+///
+/// Programs are numbered so that any point of the enumeration can be addressed directly: the
+/// programs of a given length are the numbers written in base `n`, where `n` is the amount of
+/// possible instructions, with each digit being the index of an instruction. This is what makes
+/// it possible to checkpoint the search and resume it later, and to split it into shards: each
+/// shard only checks its own slice of the programs of every length, so several processes can
+/// share one search without talking to each other.
+///
+/// Consecutive programs share everything but their last few instructions, so the state after
+/// each prefix of the current program is kept around, and the programs sharing a prefix have
+/// their last instruction run on many prefixes at once.
 pub struct ExhaustiveOptimizer {
     pub args: OptimizerArgs,
+
+    /// Every instruction a program can be made of.
+    pub instructions: Vec<Instruction>,
+
+    /// Where to write checkpoints to, and how often.
+    pub checkpoint: Option<(PathBuf, Duration)>,
+
+    /// Where to start the enumeration from, as a program length and an index.
+    pub start: (usize, u128),
+
+    /// Which slice of the programs to check, as a 0-based index and the amount of slices.
+    pub shard: (usize, usize),
+
+    /// Position of each instruction in [`ExhaustiveOptimizer::instructions`].
+    indices: HashMap<Instruction, usize>,
 }
 
 impl Optimizer for ExhaustiveOptimizer {
     fn new(args: OptimizerArgs) -> Self {
-        Self { args }
+        let instructions = args.space.instructions();
+
+        let indices = instructions
+            .iter()
+            .enumerate()
+            .map(|(i, &ins)| (ins, i))
+            .collect();
+
+        Self {
+            args,
+            instructions,
+            checkpoint: None,
+            start: (1, 0),
+            shard: (0, 1),
+            indices,
+        }
     }
 
     fn start_optimization<'a>(&'a mut self, _: &Scope<'a>) {
-        let programs = self.generate_programs();
         let counter = self.args.counter.clone();
+        let mut last_checkpoint = Instant::now();
+
+        let (mut length, mut index) = self.start;
+
+        if length < self.args.min_instructions {
+            (length, index) = (self.args.min_instructions, 0);
+        }
+
+        // the bound is recomputed after every length, as it may have changed
+        while length <= self.args.max_length() {
+            let (first, end) = self.shard_range(length);
 
-        programs
-            .into_iter()
-            .par_bridge()
-            .try_for_each(|program| -> Result<(), anyhow::Error> {
-                // if we need to stop, throw an error.
-                // since we're using try_for_each, throwing an error
-                // will cause the function to stop.
-                //
-                // there's literally no other way to stop.
+            index = index.max(first);
+
+            while index < end {
                 if self.should_stop() {
-                    return Err(anyhow!("Forcefully stopping"));
+                    self.save_checkpoint(length, index);
+                    return;
                 }
 
-                // compute the state of the program and compare it to the target state
-                let state = VM::compute_state(&program);
+                let chunk = (end - index).min(CHUNK_SIZE as u128) as u64;
+
+                // split the chunk into blocks of consecutive programs, which are walked
+                // through in parallel
+                (0..chunk.div_ceil(BLOCK_SIZE))
+                    .into_par_iter()
+                    .for_each(|block| {
+                        let offset = block * BLOCK_SIZE;
+                        let count = BLOCK_SIZE.min(chunk - offset);
 
-                // let's check if the state we just computed is equal to our target_state
-                if self.args.target == state {
-                    // we now need to check if this program is shorter than the given program
-                    // (there is a chance that it's not, depending on the options)
-                    if program.instructions.len() < self.current_optimal_length() {
-                        // since the program we found is more efficient, we update the optimal
-                        // program to be the one we just found.
+                        let checked = self.walk(length, index + offset as u128, count);
 
-                        eprintln!(
-                            "Found more optimal program ({} instructions)",
-                            program.instructions.len()
-                        );
+                        // increment the counter
+                        counter.fetch_add(checked, Ordering::Relaxed);
+                    });
+
+                // if we were stopped halfway through the chunk, we'll go through it again when
+                // resuming, so we only move on once it's done
+                if self.should_stop() {
+                    continue;
+                }
 
-                        {
-                            let mut lock = self.args.optimal.write().unwrap();
+                index += chunk as u128;
 
-                            let _ = mem::replace(&mut *lock, program);
-                        }
+                if let Some((_, interval)) = &self.checkpoint {
+                    if last_checkpoint.elapsed() >= *interval {
+                        self.save_checkpoint(length, index);
+                        last_checkpoint = Instant::now();
                     }
                 }
+            }
+
+            length += 1;
+            index = 0;
+        }
+
+        self.save_checkpoint(length, index);
 
-                // increment the counter
-                counter.fetch_add(1, Ordering::Relaxed);
+        // a shard only knows about its own slice of the programs, so proving that the optimal
+        // program is optimal is left to merging the shards. shorter programs than
+        // min_instructions weren't checked either, and neither were programs outside of the
+        // space, such as the one we started from may be.
+        let optimal = self.args.optimal.read().unwrap().clone();
 
-                Ok(())
-            })
-            .ok();
+        if self.shard.1 == 1
+            && self.args.min_instructions <= 1
+            && self.args.space.contains_program(&optimal)
+            && checkpoint::proves_optimal(&optimal, length, &*self.args.cost)
+        {
+            self.args.proven_optimal.store(true, Ordering::Relaxed);
+        }
     }
 
     fn current_optimal_length(&self) -> usize {
@@ -87,61 +172,353 @@ impl Optimizer for ExhaustiveOptimizer {
 }
 
 impl ExhaustiveOptimizer {
-    fn generate_programs(&self) -> impl Iterator<Item = Program> + '_ {
-        let max_length = self.args.max_instructions;
+    /// Periodically saves the progress of the search to the given file.
+    pub fn with_checkpoint(mut self, path: PathBuf, interval: Duration) -> Self {
+        self.checkpoint = Some((path, interval));
+        self
+    }
 
-        (1..=max_length).flat_map(move |length| {
-            INSTRUCTIONS
-                .iter()
-                .combinations_with_replacement(length)
-                .flat_map(move |inst_combo| {
-                    inst_combo
-                        .iter()
-                        .map(|&inst| self.gen_arg_sets(inst))
-                        .multi_cartesian_product()
-                        .map(move |args| Program {
-                            instructions: inst_combo
-                                .iter()
-                                .zip(args)
-                                .map(|(&inst, args)| self.create_instruction(inst, args))
-                                .collect(),
-                        })
-                })
+    /// Continues a search from where the checkpoint left off.
+    ///
+    /// The checkpoint's best program and counter are not restored here, since they're part of
+    /// the [`OptimizerArgs`].
+    pub fn resume_from(mut self, checkpoint: &Checkpoint) -> Self {
+        self.start = (checkpoint.length, checkpoint.index);
+        self.shard = checkpoint.shard;
+        self
+    }
+
+    /// Only checks the `index`th of `count` equally sized slices of the programs of each length.
+    pub fn with_shard(mut self, index: usize, count: usize) -> Self {
+        assert!(index < count, "shard index out of range");
+
+        self.shard = (index, count);
+        self
+    }
+
+    /// The range of indices of the programs with the given length which this shard checks.
+    pub fn shard_range(&self, length: usize) -> (u128, u128) {
+        let total = self.total(length);
+        let (index, count) = (self.shard.0 as u128, self.shard.1 as u128);
+
+        // split without overflowing, even when the total is close to the largest u128
+        let bound = |i: u128| total / count * i + total % count * i / count;
+
+        (bound(index), bound(index + 1))
+    }
+
+    /// Amount of programs with the given length.
+    pub fn total(&self, length: usize) -> u128 {
+        (self.instructions.len() as u128)
+            .checked_pow(length as u32)
+            .expect("search space is too large")
+    }
+
+    /// Returns the program at the given index of the programs with the given length.
+    pub fn unrank(&self, length: usize, index: u128) -> Program {
+        let mut instructions = Vec::with_capacity(length);
+
+        self.unrank_into(length, index, &mut instructions);
+
+        Program { instructions }
+    }
+
+    /// Same as [`ExhaustiveOptimizer::unrank`], but writes the instructions to the given
+    /// buffer instead of allocating a new one.
+    pub fn unrank_into(&self, length: usize, mut index: u128, instructions: &mut Vec<Instruction>) {
+        let n = self.instructions.len() as u128;
+
+        instructions.clear();
+        instructions.resize(length, self.instructions[0]);
+
+        for ins in instructions.iter_mut().rev() {
+            *ins = self.instructions[(index % n) as usize];
+            index /= n;
+        }
+    }
+
+    /// Returns the index of the program among the programs with the same length, or `None` if
+    /// it contains instructions which aren't being enumerated.
+    pub fn rank(&self, program: &Program) -> Option<u128> {
+        let n = self.instructions.len() as u128;
+
+        program.instructions.iter().try_fold(0, |index, ins| {
+            Some(index * n + *self.indices.get(ins)? as u128)
         })
     }
 
-    fn gen_arg_sets(&self, instruction: &str) -> Vec<[usize; 2]> {
-        match instruction {
-            "LOAD" => (0..=self.args.max_num as usize)
-                .map(|val| [val, 0])
-                .collect_vec(),
+    /// Checks `count` consecutive programs of the given length, starting at `start`. Returns
+    /// the amount of programs checked, which is less than `count` if we were stopped.
+    ///
+    /// Programs sharing everything but their last instruction are checked together, by running
+    /// each last instruction on a [`Batch`] of the states their prefixes leave behind. The
+    /// programs at the edges of the range, whose prefixes are only partly in it, are checked
+    /// one at a time.
+    fn walk(&self, length: usize, start: u128, count: u64) -> u64 {
+        let n = self.instructions.len() as u128;
+        let end = start + count as u128;
+
+        let batched_start = start.next_multiple_of(n).min(end);
+        let batched_end = (end - end % n).max(batched_start);
+
+        let mut checked = self.walk_one_by_one(length, start, (batched_start - start) as u64);
+
+        if batched_start < batched_end && !self.should_stop() {
+            checked += self.walk_batched(length, batched_start / n, batched_end / n);
+        }
+
+        if batched_end < end && !self.should_stop() {
+            checked += self.walk_one_by_one(length, batched_end, (end - batched_end) as u64);
+        }
 
-            "SWAP" | "XOR" | "ADD" | "SUB" => (0..MEM_SIZE)
-                .cartesian_product(0..MEM_SIZE)
-                .map(|(a, b)| [a, b])
+        checked
+    }
+
+    fn walk_one_by_one(&self, length: usize, start: u128, count: u64) -> u64 {
+        let mut programs = Enumeration::new(self, length, start);
+
+        for checked in 0..count {
+            if self.should_stop() {
+                return checked;
+            }
+
+            // let's check if the state we just computed is equal to our target_state. if it
+            // is, it becomes the optimal program as long as it's cheaper (there is a chance
+            // that it's not, depending on the options)
+            if *programs.state() == self.args.target {
+                self.args.submit(programs.program());
+            }
+
+            programs.advance();
+        }
+
+        count
+    }
+
+    /// Checks every program whose first `length - 1` instructions are one of the prefixes
+    /// with indices `start..end`.
+    fn walk_batched(&self, length: usize, start: u128, end: u128) -> u64 {
+        let n = self.instructions.len() as u64;
+
+        let mut prefixes = Enumeration::new(self, length - 1, start);
+        let mut first = start;
+        let mut checked = 0;
+
+        while first < end {
+            if self.should_stop() {
+                return checked;
+            }
+
+            let lanes = (end - first).min(LANES as u128) as usize;
+
+            let mut batch = Batch::default();
+
+            for lane in 0..lanes {
+                batch.set_state(lane, prefixes.state());
+                prefixes.advance();
+            }
+
+            let used = ALL_LANES >> (LANES - lanes);
+
+            for &ins in &self.instructions {
+                let mut next = batch;
+                next.execute_instruction(ins);
+
+                let mut matching = next.matching(&self.args.target) & used;
+
+                while matching != 0 {
+                    let lane = matching.trailing_zeros();
+                    matching &= matching - 1;
+
+                    let mut program = self.unrank(length - 1, first + lane as u128);
+                    program.instructions.push(ins);
+
+                    self.args.submit(program);
+                }
+            }
+
+            first += lanes as u128;
+            checked += lanes as u64 * n;
+        }
+
+        checked
+    }
+
+    fn save_checkpoint(&self, length: usize, index: u128) {
+        let Some((path, _)) = &self.checkpoint else {
+            return;
+        };
+
+        let checkpoint = Checkpoint {
+            target: self.args.target,
+            space: self.args.space.clone(),
+            max_instructions: self.args.max_instructions,
+            cost: self.args.cost.description(),
+            shard: self.shard,
+            length,
+            index,
+            counter: self.args.counter.load(Ordering::Relaxed),
+            best: self.args.optimal.read().unwrap().clone(),
+        };
+
+        if let Err(err) = checkpoint.save(path) {
+            eprintln!("Failed to save checkpoint: {:#}", err);
+        }
+    }
+}
+
+/// Walks through consecutive programs of the same length, keeping the state after each prefix
+/// of the current program so that moving on to the next one only runs the instructions which
+/// changed.
+struct Enumeration<'a> {
+    optimizer: &'a ExhaustiveOptimizer,
+
+    /// The index of the instruction at each position of the current program.
+    digits: Vec<usize>,
+
+    /// `states[i]` is the state after the first `i` instructions. The ones up to
+    /// `states[valid]` are up to date.
+    states: Vec<State>,
+    valid: usize,
+}
+
+impl<'a> Enumeration<'a> {
+    fn new(optimizer: &'a ExhaustiveOptimizer, length: usize, start: u128) -> Self {
+        let digits = optimizer
+            .unrank(length, start)
+            .instructions
+            .iter()
+            .map(|ins| optimizer.indices[ins])
+            .collect();
+
+        Self {
+            optimizer,
+            digits,
+            states: vec![[0; MEM_SIZE]; length + 1],
+            valid: 0,
+        }
+    }
+
+    /// The state after running the current program.
+    fn state(&mut self) -> &State {
+        let length = self.digits.len();
+
+        for i in self.valid..length {
+            self.states[i + 1] = step(&self.states[i], self.optimizer.instructions[self.digits[i]]);
+        }
+
+        self.valid = length;
+
+        &self.states[length]
+    }
+
+    fn program(&self) -> Program {
+        Program {
+            instructions: self
+                .digits
+                .iter()
+                .map(|&i| self.optimizer.instructions[i])
                 .collect(),
+        }
+    }
+
+    /// Moves on to the next program, like incrementing a number in base `n`. Every
+    /// instruction before the last one that changed keeps its state.
+    fn advance(&mut self) {
+        let n = self.optimizer.instructions.len();
+
+        let mut i = self.digits.len();
 
-            "INC" | "DECR" => (0..MEM_SIZE).map(|val| [val, 0]).collect(),
+        while i > 0 {
+            i -= 1;
+            self.digits[i] += 1;
 
-            _ => panic!("Unknown instruction: {}", instruction),
+            if self.digits[i] < n {
+                break;
+            }
+
+            self.digits[i] = 0;
         }
+
+        self.valid = self.valid.min(i);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        cost::InstructionCount,
+        space::{parse_addresses, InstructionSpace},
+    };
+
+    use super::*;
+
+    /// An optimizer enumerating the instructions which only use cells 0 and 1, with LOAD values
+    /// of up to 1.
+    fn optimizer() -> ExhaustiveOptimizer {
+        let space = InstructionSpace::new(1).with_addresses(parse_addresses("0-1").unwrap());
+        let program = Program {
+            instructions: vec![Instruction::Inc(1)],
+        };
+
+        ExhaustiveOptimizer::new(OptimizerArgs::new(
+            program,
+            space,
+            Arc::new(InstructionCount),
+            4,
+        ))
     }
 
-    fn create_instruction(&self, inst: &str, args: [usize; 2]) -> Instruction {
-        match inst {
-            "LOAD" => Instruction::Load(args[0] as MemValue),
+    #[test]
+    fn rank_inverts_unrank() {
+        let optimizer = optimizer();
 
-            "SWAP" => Instruction::Swap(args[0], args[1]),
+        for length in 0..=3 {
+            for index in 0..optimizer.total(length) {
+                let program = optimizer.unrank(length, index);
 
-            "XOR" => Instruction::XOR(args[0], args[1]),
+                assert_eq!(program.instructions.len(), length);
+                assert_eq!(optimizer.rank(&program), Some(index));
+            }
+        }
+    }
 
-            "INC" => Instruction::Inc(args[0]),
-            "DECR" => Instruction::Decr(args[0]),
+    #[test]
+    fn shards_cover_every_program_once() {
+        for length in 0..=3 {
+            // the amount of programs of each length is 22^length, which most of these don't
+            // divide evenly
+            for count in 1..=9 {
+                let mut next = 0;
 
-            "ADD" => Instruction::Add(args[0], args[1]),
-            "SUB" => Instruction::Sub(args[0], args[1]),
+                for index in 0..count {
+                    let (first, end) = optimizer().with_shard(index, count).shard_range(length);
 
-            _ => panic!("Unknown instruction: {}", inst),
+                    assert_eq!(
+                        first, next,
+                        "shard {}/{} of length {}",
+                        index, count, length
+                    );
+                    assert!(end >= first);
+
+                    next = end;
+                }
+
+                assert_eq!(next, optimizer().total(length));
+            }
         }
     }
+
+    #[test]
+    fn rank_rejects_instructions_outside_of_the_space() {
+        let optimizer = optimizer();
+
+        let program = Program {
+            instructions: vec![Instruction::Inc(1), Instruction::Inc(2)],
+        };
+
+        assert_eq!(optimizer.rank(&program), None);
+    }
 }
@@ -1,18 +1,29 @@
-use std::sync::{
-    atomic::{AtomicBool, AtomicU64},
-    Arc, RwLock,
+use std::{
+    mem,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
 };
 
 use rayon::Scope;
 use superr_vm::{
     program::Program,
-    vm::{MemValue, State},
+    vm::{State, VM},
 };
 
+use crate::{
+    cost::CostModel, history::SolutionHistory, pareto::ParetoArchive, space::InstructionSpace,
+};
+
+pub mod beam;
 pub mod diffing;
 pub mod exhaustive;
+pub mod portfolio;
 pub mod random_search;
+pub mod window;
 
+#[derive(Clone)]
 pub struct OptimizerArgs {
     /// Target state which we want our program to have.
     pub target: State,
@@ -20,39 +31,212 @@ pub struct OptimizerArgs {
     /// Length of the program we're trying to optimize.
     pub length: usize,
 
-    /// Largest possible number that can appear in instructions such as LOAD.
-    pub max_num: MemValue,
+    /// Instructions which the optimizers may use.
+    pub space: InstructionSpace,
+
+    /// Length of the shortest programs to generate, for when it's known that there are no
+    /// shorter solutions. The window optimizer only rewrites parts of the optimal program, so
+    /// it ignores this.
+    pub min_instructions: usize,
 
-    /// Max amount of instructions a program should have. Typically our
-    /// original program's length minus 1.
+    /// Max amount of instructions a program should have, as given by the user.
+    ///
+    /// The optimizers shouldn't use this directly, but [`OptimizerArgs::max_length`], which also
+    /// rules out programs too long to be cheaper than the optimal one. With the length cost,
+    /// that's the optimal program's length minus 1.
     pub max_instructions: usize,
 
+    /// Cached value of [`OptimizerArgs::max_length`], so that workers can read it without
+    /// taking any locks. It's updated whenever a program is submitted.
+    max_length: Arc<AtomicUsize>,
+
     /// Container for our most optimal program.
     ///
-    /// NOTE: We could have a history rather than storing a single
-    /// optimal program and discarding the others.
+    /// Other correct programs are discarded, unless a [`OptimizerArgs::history`]
+    /// is given.
     pub optimal: Arc<RwLock<Program>>,
 
+    /// What we're trying to minimize.
+    pub cost: Arc<dyn CostModel>,
+
+    /// When optimizing for several costs at once, every correct program found is offered
+    /// to this archive, which keeps the ones on the Pareto front. The optimizers then keep going
+    /// for as long as programs could still make it onto the front, see
+    /// [`OptimizerArgs::max_length`].
+    pub archive: Option<Arc<Mutex<ParetoArchive>>>,
+
+    /// If given, every distinct correct program found is recorded here.
+    pub history: Option<Arc<Mutex<SolutionHistory>>>,
+
     /// Counter for the amount of programs checked.
     ///
     /// This is used for the progress bar and other statistics.
     pub counter: Arc<AtomicU64>,
 
+    /// Set once an optimizer has shown that no program cheaper than the optimal one exists,
+    /// among the programs it can generate.
+    pub proven_optimal: Arc<AtomicBool>,
+
     /// Switch for stopping the optimization process.
     ///
     /// This is used for the interface.
     pub should_stop: Arc<AtomicBool>,
 }
 
+impl OptimizerArgs {
+    /// Arguments for optimizing the given program, which is the optimal program until a
+    /// cheaper one is found.
+    pub fn new(
+        program: Program,
+        space: InstructionSpace,
+        cost: Arc<dyn CostModel>,
+        max_instructions: usize,
+    ) -> Self {
+        let args = Self {
+            target: VM::compute_state(&program),
+            length: program.instructions.len(),
+            space,
+            min_instructions: 0,
+            max_instructions,
+            max_length: Arc::new(AtomicUsize::new(max_instructions)),
+            optimal: Arc::new(RwLock::new(program)),
+            cost,
+            archive: None,
+            history: None,
+            counter: Arc::new(AtomicU64::default()),
+            proven_optimal: Arc::new(AtomicBool::default()),
+            should_stop: Arc::new(AtomicBool::default()),
+        };
+
+        args.update_max_length();
+        args
+    }
+
+    /// Only generates programs of at least `min_instructions` instructions.
+    pub fn with_min_instructions(mut self, min_instructions: usize) -> Self {
+        self.min_instructions = min_instructions;
+        self
+    }
+
+    /// Offers every correct program found to the given Pareto archive.
+    pub fn with_archive(mut self, archive: Arc<Mutex<ParetoArchive>>) -> Self {
+        self.archive = Some(archive);
+
+        // the optimizers keep going for as long as programs can make it onto the front, so the
+        // bound may be longer now
+        self.max_length = Arc::new(AtomicUsize::new(self.max_instructions));
+        self.update_max_length();
+        self
+    }
+
+    /// Records every distinct correct program found in the given history.
+    pub fn with_history(mut self, history: Arc<Mutex<SolutionHistory>>) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    /// Cost of our current optimal program.
+    pub fn optimal_cost(&self) -> u64 {
+        self.cost.cost(&self.optimal.read().unwrap().instructions)
+    }
+
+    /// Length of the longest program which could still be cheaper than our current optimal
+    /// program, or be added to the Pareto archive if there is one, capped at
+    /// [`OptimizerArgs::max_instructions`].
+    ///
+    /// An optimal program outside of the space doesn't bound anything, since any program
+    /// inside of it replaces it.
+    pub fn max_length(&self) -> usize {
+        self.max_length.load(Ordering::Relaxed)
+    }
+
+    /// Recomputes [`OptimizerArgs::max_length`]. The bound only ever goes down, as programs
+    /// cheaper than the optimal one (or than entries of the archive) are submitted, so a bound
+    /// computed from an older optimal program never replaces a newer one.
+    fn update_max_length(&self) {
+        let in_space = self.space.contains_program(&self.optimal.read().unwrap());
+
+        let max_length = match self.cost.min_instruction_cost() {
+            _ if !in_space => self.max_instructions,
+            0 => self.max_instructions,
+            min_cost => self
+                .max_instructions
+                .min((self.optimal_cost().saturating_sub(1) / min_cost) as usize),
+        };
+
+        let max_length = match &self.archive {
+            Some(archive) => {
+                max_length.max(archive.lock().unwrap().max_length(self.max_instructions))
+            }
+            None => max_length,
+        };
+
+        self.max_length.fetch_min(max_length, Ordering::Relaxed);
+    }
+
+    /// Replaces the optimal program with the given one if it's cheaper, and offers it to the
+    /// Pareto archive and history if there are any.
+    ///
+    /// The program we start from may use instructions outside of the space, as may the ones the
+    /// window optimizer makes out of it. Any program inside of the space replaces such a
+    /// program, however expensive it is.
+    ///
+    /// The program must already be known to reach the target state. Returns whether the
+    /// optimal program was replaced.
+    pub fn submit(&self, program: Program) -> bool {
+        let cost = self.cost.cost(&program.instructions);
+        let outside = !self.space.contains_program(&program);
+
+        // programs outside of the space are never reported
+        if !outside {
+            if let Some(archive) = &self.archive {
+                if archive.lock().unwrap().insert(program.clone()) {
+                    self.update_max_length();
+                }
+            }
+
+            if let Some(history) = &self.history {
+                history.lock().unwrap().record(program.clone(), cost);
+            }
+        }
+
+        let mut lock = self.optimal.write().unwrap();
+
+        if (outside, cost)
+            >= (
+                !self.space.contains_program(&lock),
+                self.cost.cost(&lock.instructions),
+            )
+        {
+            return false;
+        }
+
+        eprintln!(
+            "Found more optimal program ({} instructions, {}: {})",
+            program.instructions.len(),
+            self.cost.name(),
+            cost
+        );
+
+        let _ = mem::replace(&mut *lock, program);
+        drop(lock);
+
+        self.update_max_length();
+
+        true
+    }
+}
+
 pub trait Optimizer {
     /// Creates a new instance of the Optimizer.
-    fn new(args: OptimizerArgs) -> Self;
+    fn new(args: OptimizerArgs) -> Self
+    where
+        Self: Sized;
 
     /// Starts the optimization process.
     ///
-    /// It starts multiple threads using rayon:
-    ///   - one for reporting the progress
-    ///   - and the rest of the available threads, for computing the optimal program.
+    /// It uses the threads of the rayon pool it's started in for computing the optimal
+    /// program. Reporting the progress is left to the caller, on a thread of its own.
     ///
     /// It also joins the threads, meaning that this function is blocking, until
     /// the threads are stopped.
@@ -77,3 +261,53 @@ pub trait Optimizer {
     /// programs until it finds an optimal program.
     fn worker_loop(&self);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use superr_vm::instruction::Opcode;
+
+    use crate::cost::InstructionCount;
+
+    fn program(source: &str) -> Program {
+        source.parse().unwrap()
+    }
+
+    fn args(program: Program, space: InstructionSpace) -> OptimizerArgs {
+        OptimizerArgs::new(program, space, Arc::new(InstructionCount), 8)
+    }
+
+    #[test]
+    fn submitting_lowers_the_bound() {
+        let args = args(
+            program("INC 1\nINC 1\nINC 1\nINC 1"),
+            InstructionSpace::new(8),
+        );
+
+        assert_eq!(args.max_length(), 3);
+
+        // the bound is shared between clones, such as the members of a portfolio
+        let member = args.clone();
+
+        assert!(member.submit(program("LOAD 4\nSWAP 0 1")));
+        assert_eq!(args.max_length(), 1);
+
+        assert!(!args.submit(program("INC 1\nINC 1\nINC 1")));
+        assert_eq!(args.max_length(), 1);
+    }
+
+    #[test]
+    fn programs_outside_of_the_space_dont_bound_the_search() {
+        let space = InstructionSpace::new(8).without(Opcode::Swap);
+        let args = args(program("LOAD 3\nSWAP 0 5\nSWAP 5 1"), space);
+
+        assert_eq!(args.max_length(), 8);
+
+        // inside of the space, so it replaces the program we started from even though it
+        // isn't any cheaper
+        assert!(args.submit(program("INC 1\nINC 1\nINC 1")));
+        assert_eq!(args.max_length(), 2);
+
+        assert!(!args.submit(program("LOAD 3\nSWAP 0 1")));
+    }
+}
@@ -0,0 +1,81 @@
+use std::{sync::atomic::Ordering, thread};
+
+use rayon::{Scope, ThreadPoolBuilder};
+
+use super::{Optimizer, OptimizerArgs};
+
+/// Runs several optimizers at the same time, each on its own share of the threads.
+///
+/// The members are all given clones of the same [`OptimizerArgs`], so they share the optimal
+/// program: as soon as one of them finds a cheaper program, the others use it to bound the
+/// programs they look at. Once one of them proves that the optimal program is optimal, all of
+/// them are stopped.
+///
+/// The threads of the pool it's started in are split between the members, so there should be at
+/// least one for each of them.
+pub struct PortfolioOptimizer {
+    pub args: OptimizerArgs,
+
+    /// The optimizers being run.
+    pub members: Vec<Box<dyn Optimizer + Send>>,
+}
+
+impl Optimizer for PortfolioOptimizer {
+    fn new(args: OptimizerArgs) -> Self {
+        Self {
+            args,
+            members: vec![],
+        }
+    }
+
+    fn start_optimization<'a>(&'a mut self, _: &Scope<'a>) {
+        if self.should_stop() || self.members.is_empty() {
+            return;
+        }
+
+        // split the threads as evenly as possible, but every member needs at least one
+        let members = self.members.len();
+        let threads = rayon::current_num_threads().max(members);
+
+        let args = &self.args;
+
+        thread::scope(|threads_scope| {
+            for (i, member) in self.members.iter_mut().enumerate() {
+                let pool = ThreadPoolBuilder::new()
+                    .num_threads(threads / members + usize::from(i < threads % members))
+                    .build()
+                    .unwrap();
+
+                threads_scope.spawn(move || {
+                    pool.scope(|scope| member.start_optimization(scope));
+
+                    // there's nothing left for the other members to find
+                    if args.proven_optimal.load(Ordering::Relaxed) {
+                        args.should_stop.store(true, Ordering::Relaxed);
+                    }
+                });
+            }
+        });
+    }
+
+    fn current_optimal_length(&self) -> usize {
+        self.args.optimal.read().unwrap().instructions.len()
+    }
+
+    fn should_stop(&self) -> bool {
+        self.args.should_stop.load(Ordering::Relaxed)
+    }
+
+    fn worker_loop(&self) {
+        // the members run their own worker loops
+    }
+}
+
+impl PortfolioOptimizer {
+    /// Adds an optimizer to the portfolio. It should have been created with a clone of the
+    /// portfolio's [`OptimizerArgs`].
+    pub fn with_member(mut self, member: Box<dyn Optimizer + Send>) -> Self {
+        self.members.push(member);
+        self
+    }
+}
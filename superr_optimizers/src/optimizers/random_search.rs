@@ -1,26 +1,19 @@
-use std::{
-    mem,
-    sync::atomic::{AtomicUsize, Ordering},
-};
+use std::sync::atomic::Ordering;
 
 use rayon::Scope;
-use superr_vm::{program::Program, vm::VM};
+use superr_vm::{instruction::Instruction, program::Program, vm::VM};
 
-use crate::generate_instruction;
+use crate::vm_pool;
 
 use super::{Optimizer, OptimizerArgs};
 
 pub struct RandomSearchOptimizer {
     pub args: OptimizerArgs,
-    pub max_instructions: AtomicUsize,
 }
 
 impl Optimizer for RandomSearchOptimizer {
     fn new(args: OptimizerArgs) -> Self {
-        Self {
-            max_instructions: AtomicUsize::new(args.max_instructions),
-            args,
-        }
+        Self { args }
     }
 
     fn start_optimization<'a>(&'a mut self, scope: &Scope<'a>) {
@@ -28,8 +21,9 @@ impl Optimizer for RandomSearchOptimizer {
             return;
         }
 
-        // run the worker threads for computing the shortest possible program
-        for _ in 0..rayon::current_num_threads() - 1 {
+        // run the worker threads for computing the shortest possible program, one for each
+        // thread of the pool we were started in
+        for _ in 0..rayon::current_num_threads() {
             scope.spawn(|_| self.worker_loop());
         }
     }
@@ -43,66 +37,57 @@ impl Optimizer for RandomSearchOptimizer {
     }
 
     fn worker_loop(&self) {
-        let mut vm = VM::default();
+        vm_pool::with_vm(|vm| self.search(vm));
+    }
+}
 
+impl RandomSearchOptimizer {
+    /// Keeps generating and running random programs on the given VM until told to stop.
+    fn search(&self, vm: &mut VM) {
         let counter = self.args.counter.clone();
 
+        // the same buffer is reused for every program, so nothing is allocated unless we find
+        // a solution
+        let mut instructions = vec![];
+
         while !self.should_stop() {
             vm.reset();
 
             // generate a completely random program, and compute its state
-            let program = self.generate_program();
-            vm.execute_program(program.clone());
+            self.generate_program(&mut instructions);
+            vm.execute(&instructions);
 
             let state = vm.state;
 
-            // let's check if the state we just computed is equal to our target_state
+            // let's check if the state we just computed is equal to our target_state. if it
+            // is, and it's cheaper than the optimal program (there is a chance that it's not,
+            // depending on the options), it becomes the new optimal program.
             if self.args.target == state {
-                // we now need to check if this program is shorter than the given program
-                // (there is a chance that it's not, depending on the options)
-                if program.instructions.len() < self.current_optimal_length() {
-                    // since the program we found is more efficient, we update the optimal
-                    // program to be the one we just found.
-
-                    let new_len = program.instructions.len();
-
-                    eprintln!("Found more optimal program ({} instructions)", new_len);
-
-                    {
-                        let mut lock = self.args.optimal.write().unwrap();
-
-                        let _ = mem::replace(&mut *lock, program);
-                    }
-
-                    // update max_instructions so we can look for programs even
-                    // shorter than what we just found
-                    self.max_instructions.store(new_len - 1, Ordering::Relaxed);
-                }
+                self.args.submit(Program {
+                    instructions: instructions.clone(),
+                });
             }
 
             // increment the counter
             counter.fetch_add(1, Ordering::Relaxed);
         }
     }
-}
 
-impl RandomSearchOptimizer {
-    /// Randomly generates a program based on the [`RandomSearchOptimizerOptions`].
-    fn generate_program(&self) -> Program {
-        let mut program = Program::new();
+    /// Randomly generates a program based on the [`OptimizerArgs`], replacing the contents
+    /// of the given buffer.
+    fn generate_program(&self, instructions: &mut Vec<Instruction>) {
+        instructions.clear();
 
         // generate a random amount of instructions for the program to have. this amount is
-        // within 0 and the given max_instructions.
-        let max_instructions = self.max_instructions.load(Ordering::Relaxed);
-        let instructions_amount = fastrand::usize(0..=max_instructions);
+        // within min_instructions and the length of the longest program which could still be
+        // cheaper than the optimal one, which other optimizers may also be lowering.
+        let max_instructions = self.args.max_length();
+        let min_instructions = self.args.min_instructions.min(max_instructions);
+        let instructions_amount = fastrand::usize(min_instructions..=max_instructions);
 
         // generate the instructions of the program
         for _ in 0..instructions_amount {
-            program
-                .instructions
-                .push(generate_instruction(self.args.max_num));
+            instructions.push(self.args.space.generate());
         }
-
-        program
     }
 }
@@ -0,0 +1,195 @@
+use std::sync::atomic::Ordering;
+
+use itertools::Itertools;
+use rayon::{
+    iter::{ParallelBridge, ParallelIterator},
+    Scope,
+};
+use superr_vm::{
+    analysis::{self, CellSet, ZERO_STATE},
+    instruction::Instruction,
+    program::Program,
+    vm::State,
+};
+
+use crate::{equivalence::TestVectors, vm_pool};
+
+use super::{Optimizer, OptimizerArgs};
+
+/// Amount of test vectors used when some of the cells at the start of a window are unknown.
+const WINDOW_VECTORS: usize = 16;
+
+/// Superoptimizes long programs a few instructions at a time.
+///
+/// The program is split into overlapping windows of [`WindowOptimizer::window`] instructions.
+/// Each window is replaced by the cheapest sequence which leaves the same values in every cell
+/// that is live after it, given what we know about the memory before it. Windows are revisited
+/// until none of them can be shortened any further.
+pub struct WindowOptimizer {
+    pub args: OptimizerArgs,
+
+    /// Amount of instructions in each window.
+    pub window: usize,
+}
+
+impl Optimizer for WindowOptimizer {
+    fn new(args: OptimizerArgs) -> Self {
+        Self { args, window: 3 }
+    }
+
+    fn start_optimization<'a>(&'a mut self, _: &Scope<'a>) {
+        if self.should_stop() {
+            return;
+        }
+
+        // windows depend on the ones before them, so they're processed one at a time; each
+        // window's search is parallelized instead
+        self.worker_loop();
+    }
+
+    fn current_optimal_length(&self) -> usize {
+        self.args.optimal.read().unwrap().instructions.len()
+    }
+
+    fn should_stop(&self) -> bool {
+        self.args.should_stop.load(Ordering::Relaxed)
+    }
+
+    fn worker_loop(&self) {
+        let mut instructions = self.args.optimal.read().unwrap().instructions.clone();
+
+        // rewriting would shift jump targets around
+        if instructions
+            .iter()
+            .any(|ins| matches!(ins, Instruction::Jmp(_)))
+        {
+            return;
+        }
+
+        // windows overlap by half of their length
+        let stride = (self.window / 2).max(1);
+
+        loop {
+            let mut changed = false;
+            let mut start = 0;
+
+            while start < instructions.len() && !self.should_stop() {
+                let end = (start + self.window).min(instructions.len());
+
+                if let Some(replacement) = self.optimize_window(&instructions, start, end) {
+                    instructions.splice(start..end, replacement);
+                    changed = true;
+
+                    self.args.submit(Program {
+                        instructions: instructions.clone(),
+                    });
+
+                    // look at the same position again, as it's now a different window
+                    continue;
+                }
+
+                start += stride;
+            }
+
+            if !changed || self.should_stop() {
+                break;
+            }
+        }
+    }
+}
+
+impl WindowOptimizer {
+    /// Sets the amount of instructions in each window.
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window.max(1);
+        self
+    }
+
+    /// Looks for a cheaper replacement for the instructions in `start..end`.
+    fn optimize_window(
+        &self,
+        instructions: &[Instruction],
+        start: usize,
+        end: usize,
+    ) -> Option<Vec<Instruction>> {
+        let window = &instructions[start..end];
+
+        // PUT has side effects which a replacement would have to preserve
+        if window.iter().any(analysis::has_side_effects) {
+            return None;
+        }
+
+        let live_out = analysis::liveness(instructions, CellSet::ALL)[end];
+        let entry = analysis::constants(instructions, ZERO_STATE)[start];
+
+        let vectors = TestVectors::with_entry(&entry, WINDOW_VECTORS, start as u64);
+        let expected = vectors.fingerprint(window);
+
+        // only consider instructions working on the cells the window already uses, which keeps
+        // the search space small. LOAD always writes to cell 0, so we allow that one too.
+        let cells = window
+            .iter()
+            .map(analysis::def_use)
+            .fold(CellSet::single(0), |cells, du| {
+                cells.union(du.defs).union(du.uses)
+            });
+
+        let candidates = self
+            .args
+            .space
+            .instructions()
+            .into_iter()
+            .filter(|ins| {
+                let du = analysis::def_use(ins);
+
+                du.defs.union(du.uses).difference(cells).is_empty()
+            })
+            .collect_vec();
+
+        let cost = self.args.cost.cost(window);
+
+        // instructions outside of the space (which the program we started from may have) are
+        // replaced even if that isn't cheaper, as the result couldn't be reported otherwise
+        let outside = !window.iter().all(|ins| self.args.space.contains(ins));
+
+        let matches = |candidate: &[Instruction]| {
+            self.args.counter.fetch_add(1, Ordering::Relaxed);
+
+            (outside || self.args.cost.cost(candidate) < cost)
+                && vm_pool::with_scratch(|scratch| {
+                    vectors.fingerprint_into(candidate, &mut scratch.states);
+
+                    agree_on(&expected, &scratch.states, live_out)
+                })
+        };
+
+        if matches(&[]) {
+            return Some(vec![]);
+        }
+
+        // depending on the cost model, a replacement may be as long as the window itself and
+        // still be cheaper
+        let max_length = match self.args.cost.min_instruction_cost() {
+            _ if outside => window.len(),
+            0 => window.len(),
+            min_cost => window
+                .len()
+                .min((cost.saturating_sub(1) / min_cost) as usize),
+        };
+
+        (1..=max_length).find_map(|length| {
+            (0..length)
+                .map(|_| candidates.iter().copied())
+                .multi_cartesian_product()
+                .par_bridge()
+                .find_any(|candidate| !self.should_stop() && matches(candidate))
+        })
+    }
+}
+
+/// Whether the two fingerprints agree on the given cells.
+fn agree_on(a: &[State], b: &[State], cells: CellSet) -> bool {
+    a.iter()
+        .zip(b)
+        .all(|(a, b)| cells.iter().all(|cell| a[cell] == b[cell]))
+}
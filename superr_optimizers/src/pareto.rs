@@ -0,0 +1,197 @@
+use std::sync::Arc;
+
+use superr_vm::program::Program;
+
+use crate::cost::CostModel;
+
+/// A program along with its cost under each of the archive's objectives.
+#[derive(Debug, Clone)]
+pub struct ParetoEntry {
+    pub program: Program,
+    pub costs: Vec<u64>,
+}
+
+/// Keeps every program which isn't dominated by another, when optimizing for several costs
+/// at once.
+///
+/// A program dominates another if it's at least as cheap under every objective, and cheaper
+/// under at least one. Programs with exactly the same costs as one already in the archive are
+/// not added, so there's a single program per point of the front.
+pub struct ParetoArchive {
+    objectives: Vec<Arc<dyn CostModel>>,
+    entries: Vec<ParetoEntry>,
+}
+
+impl ParetoArchive {
+    pub fn new(objectives: Vec<Arc<dyn CostModel>>) -> Self {
+        Self {
+            objectives,
+            entries: vec![],
+        }
+    }
+
+    pub fn objectives(&self) -> &[Arc<dyn CostModel>] {
+        &self.objectives
+    }
+
+    /// The non-dominated programs found so far, in the order they were found.
+    pub fn entries(&self) -> &[ParetoEntry] {
+        &self.entries
+    }
+
+    /// Computes the costs of the program, under each objective.
+    pub fn costs(&self, program: &Program) -> Vec<u64> {
+        self.objectives
+            .iter()
+            .map(|objective| objective.cost(&program.instructions))
+            .collect()
+    }
+
+    /// Adds the program to the archive, unless it's dominated by (or has the same costs as)
+    /// a program already in it. Any programs it dominates are removed.
+    ///
+    /// Returns whether the program was added.
+    pub fn insert(&mut self, program: Program) -> bool {
+        let costs = self.costs(&program);
+
+        if self
+            .entries
+            .iter()
+            .any(|entry| entry.costs == costs || dominates(&entry.costs, &costs))
+        {
+            return false;
+        }
+
+        self.entries
+            .retain(|entry| !dominates(&costs, &entry.costs));
+        self.entries.push(ParetoEntry { program, costs });
+
+        true
+    }
+
+    /// Length of the longest program which could still be added to the archive, capped at
+    /// `max_length`.
+    ///
+    /// Every instruction adds at least [`CostModel::min_instruction_cost`] to each objective, so
+    /// past some length a program can't be cheaper than an entry under any of them, and is
+    /// dominated by it (or has the same costs). Objectives without such a bound never rule out
+    /// anything, unless the entry's cost under them is 0.
+    pub fn max_length(&self, max_length: usize) -> usize {
+        self.entries
+            .iter()
+            .filter_map(|entry| {
+                // the shortest length at which the entry rules out programs
+                self.objectives.iter().zip(&entry.costs).try_fold(
+                    0,
+                    |length, (objective, &cost)| match objective.min_instruction_cost() {
+                        0 => (cost == 0).then_some(length),
+                        min_cost => Some(length.max(cost.div_ceil(min_cost))),
+                    },
+                )
+            })
+            .map(|length| (length as usize).saturating_sub(1))
+            .fold(max_length, usize::min)
+    }
+}
+
+/// Whether a program with costs `a` dominates one with costs `b`.
+pub fn dominates(a: &[u64], b: &[u64]) -> bool {
+    a.iter().zip(b).all(|(a, b)| a <= b) && a.iter().zip(b).any(|(a, b)| a < b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cost::{CellsTouched, InstructionCount};
+
+    fn program(source: &str) -> Program {
+        source.parse().unwrap()
+    }
+
+    /// An archive minimizing length and the amount of cells used.
+    fn archive() -> ParetoArchive {
+        ParetoArchive::new(vec![Arc::new(InstructionCount), Arc::new(CellsTouched)])
+    }
+
+    fn costs(archive: &ParetoArchive) -> Vec<Vec<u64>> {
+        archive
+            .entries()
+            .iter()
+            .map(|entry| entry.costs.clone())
+            .collect()
+    }
+
+    #[test]
+    fn dominance() {
+        assert!(dominates(&[1, 2], &[2, 2]));
+        assert!(dominates(&[1, 1], &[2, 2]));
+        assert!(!dominates(&[2, 2], &[2, 2]));
+        assert!(!dominates(&[1, 3], &[2, 2]));
+        assert!(!dominates(&[3, 1], &[2, 2]));
+    }
+
+    #[test]
+    fn rejects_equal_costs() {
+        let mut archive = archive();
+
+        assert!(archive.insert(program("INC 1\nINC 1")));
+        assert!(!archive.insert(program("INC 2\nINC 2")));
+
+        assert_eq!(archive.entries().len(), 1);
+        assert_eq!(archive.entries()[0].program, program("INC 1\nINC 1"));
+    }
+
+    #[test]
+    fn rejects_dominated_programs() {
+        let mut archive = archive();
+
+        assert!(archive.insert(program("INC 1")));
+        assert!(!archive.insert(program("INC 1\nINC 1")));
+        assert!(!archive.insert(program("SWAP 1 2")));
+
+        assert_eq!(costs(&archive), vec![vec![1, 1]]);
+    }
+
+    #[test]
+    fn keeps_programs_cheaper_under_different_objectives() {
+        let mut archive = archive();
+
+        // shorter, but uses more cells
+        assert!(archive.insert(program("SWAP 1 2")));
+        // longer, but uses fewer cells
+        assert!(archive.insert(program("INC 1\nINC 1")));
+
+        assert_eq!(costs(&archive), vec![vec![1, 2], vec![2, 1]]);
+    }
+
+    #[test]
+    fn evicts_dominated_programs() {
+        let mut archive = archive();
+
+        assert!(archive.insert(program("SWAP 1 2")));
+        assert!(archive.insert(program("INC 1\nINC 1")));
+
+        // as cheap as either under one objective, and cheaper under the other
+        assert!(archive.insert(program("INC 1")));
+
+        assert_eq!(costs(&archive), vec![vec![1, 1]]);
+        assert_eq!(archive.entries()[0].program, program("INC 1"));
+    }
+
+    #[test]
+    fn max_length() {
+        let mut archive = archive();
+
+        assert_eq!(archive.max_length(10), 10);
+
+        // cells have no per-instruction bound, so only a program using no cells rules out
+        // longer ones
+        archive.insert(program("INC 1\nINC 1"));
+        assert_eq!(archive.max_length(10), 10);
+
+        archive.insert(Program {
+            instructions: vec![],
+        });
+        assert_eq!(archive.max_length(10), 0);
+    }
+}
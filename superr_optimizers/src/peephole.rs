@@ -0,0 +1,408 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{bail, Context};
+use itertools::Itertools;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use superr_vm::{
+    instruction::Instruction,
+    program::Program,
+    vm::{MemValue, State},
+};
+
+use crate::{equivalence::TestVectors, space::InstructionSpace};
+
+/// Amount of test vectors used to fingerprint windows while learning rules.
+const FINGERPRINT_VECTORS: usize = 8;
+
+/// Amount of test vectors used to double check a rule before recording it.
+const VERIFY_VECTORS: usize = 256;
+
+/// Amount of windows fingerprinted at once while learning rules.
+const LEARN_CHUNK: usize = 1 << 16;
+
+/// A database of rewrite rules, each mapping a short window of instructions to a shorter
+/// sequence which has the same effect on memory.
+///
+/// Rules are tested rather than proven: they're only checked on a fixed set of random memory
+/// states (see [`RuleDatabase::learn`]), so programs rewritten with them should be double
+/// checked against their target.
+///
+/// Rules are stored in a plain text file, one per line, with the instructions of the window and
+/// of the replacement separated by commas:
+///
+/// ```text
+/// SWAP 0 1, SWAP 1 0 =>
+/// LOAD 3, LOAD 2 => LOAD 2
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RuleDatabase {
+    rules: HashMap<Vec<Instruction>, Vec<Instruction>>,
+
+    /// Length of the longest window in the database.
+    max_window: usize,
+}
+
+impl RuleDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a rule to the database. Fails if the replacement isn't shorter than the window,
+    /// since rewriting with such a rule might never terminate.
+    pub fn insert(
+        &mut self,
+        window: Vec<Instruction>,
+        replacement: Vec<Instruction>,
+    ) -> anyhow::Result<()> {
+        if replacement.len() >= window.len() {
+            bail!("rule doesn't make programs shorter");
+        }
+
+        self.insert_unchecked(window, replacement);
+
+        Ok(())
+    }
+
+    /// Adds all of the rules of another database to this one.
+    pub fn extend(&mut self, other: RuleDatabase) {
+        for (window, replacement) in other.rules {
+            self.insert_unchecked(window, replacement);
+        }
+    }
+
+    pub fn get(&self, window: &[Instruction]) -> Option<&Vec<Instruction>> {
+        self.rules.get(window)
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Loads a database from a rules file. Empty lines and lines starting with `;` are ignored.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let contents = fs::read_to_string(path).context("couldn't read rules file")?;
+
+        let mut database = Self::new();
+
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+
+            let (window, replacement) = line
+                .split_once("=>")
+                .with_context(|| format!("invalid rule: {}", line))?;
+
+            let window =
+                parse_sequence(window).with_context(|| format!("invalid rule: {}", line))?;
+            let replacement =
+                parse_sequence(replacement).with_context(|| format!("invalid rule: {}", line))?;
+
+            database
+                .insert(window, replacement)
+                .with_context(|| format!("invalid rule: {}", line))?;
+        }
+
+        Ok(database)
+    }
+
+    /// Saves the database to a rules file, shortest windows first.
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let contents = self
+            .rules
+            .iter()
+            .map(|(window, replacement)| {
+                let line = format!(
+                    "{} => {}",
+                    format_sequence(window),
+                    format_sequence(replacement)
+                );
+
+                (window.len(), line.trim_end().to_string())
+            })
+            .sorted()
+            .map(|(_, line)| line + "\n")
+            .collect::<String>();
+
+        fs::write(path, contents).context("couldn't write rules file")
+    }
+
+    /// Rewrites the program using the rules in the database, until no more rules apply.
+    ///
+    /// At every position the longest matching window is rewritten first. Since every rule makes
+    /// the program shorter, this always terminates. Programs containing a JMP are returned as-is,
+    /// since rewriting would shift the jump targets.
+    pub fn apply(&self, program: &Program) -> Program {
+        let mut instructions = program.instructions.clone();
+
+        if instructions
+            .iter()
+            .any(|ins| matches!(ins, Instruction::Jmp(_)))
+        {
+            return program.clone();
+        }
+
+        loop {
+            let mut rewritten = Vec::with_capacity(instructions.len());
+            let mut changed = false;
+
+            let mut i = 0;
+
+            'outer: while i < instructions.len() {
+                for size in (1..=self.max_window.min(instructions.len() - i)).rev() {
+                    if let Some(replacement) = self.get(&instructions[i..i + size]) {
+                        rewritten.extend_from_slice(replacement);
+                        changed = true;
+                        i += size;
+
+                        continue 'outer;
+                    }
+                }
+
+                rewritten.push(instructions[i]);
+                i += 1;
+            }
+
+            instructions = rewritten;
+
+            if !changed {
+                break;
+            }
+        }
+
+        Program { instructions }
+    }
+
+    /// Learns rules by superoptimizing every window of up to `max_length` instructions.
+    ///
+    /// Windows are enumerated exhaustively in order of length, and fingerprinted by running them
+    /// on a fixed set of test vectors. Whenever a window has the same fingerprint as a shorter one
+    /// we've already seen, the shorter one is a candidate replacement, which becomes a rule if it
+    /// also agrees with the window on a larger set of test vectors. Windows which contain a
+    /// window we already have a rule for are skipped, since the engine would rewrite that first.
+    ///
+    /// Windows are processed in fixed size chunks, so that memory use doesn't grow with the
+    /// amount of windows.
+    ///
+    /// The amount of windows grows exponentially with `max_length`, so anything above 2 takes a
+    /// very long time unless `max_num` is small.
+    pub fn learn(max_length: usize, max_num: MemValue) -> Self {
+        let instructions = InstructionSpace::new(max_num).instructions();
+
+        let vectors = TestVectors::new(FINGERPRINT_VECTORS, 0);
+        let verification = TestVectors::new(VERIFY_VECTORS, 1);
+
+        // shortest known window for each fingerprint
+        let mut shortest: HashMap<Vec<State>, Vec<Instruction>> = HashMap::new();
+        shortest.insert(vectors.fingerprint(&[]), vec![]);
+
+        let mut database = Self::new();
+
+        for length in 1..=max_length {
+            let windows = (0..length)
+                .map(|_| instructions.iter().copied())
+                .multi_cartesian_product();
+
+            let mut learned = 0;
+
+            // the chunks are fingerprinted in parallel, but merged in order so that the rules
+            // we end up with don't depend on scheduling
+            for chunk in &windows.chunks(LEARN_CHUNK) {
+                let found = chunk
+                    .collect::<Vec<_>>()
+                    .into_par_iter()
+                    .filter(|window| !database.contains_rule_for(window))
+                    .map(|window| {
+                        let fingerprint = vectors.fingerprint(&window);
+
+                        (window, fingerprint)
+                    })
+                    .collect::<Vec<_>>();
+
+                for (window, fingerprint) in found {
+                    match shortest.get(&fingerprint) {
+                        Some(replacement) if replacement.len() < window.len() => {
+                            if verification.equivalent(&window, replacement) {
+                                database.insert_unchecked(window, replacement.clone());
+                                learned += 1;
+                            }
+                        }
+
+                        Some(_) => {}
+
+                        None => {
+                            // windows of the last length can't be a replacement for anything
+                            if length < max_length {
+                                shortest.insert(fingerprint, window);
+                            }
+                        }
+                    }
+                }
+            }
+
+            eprintln!("Learned {} rules for windows of length {}", learned, length);
+        }
+
+        database
+    }
+
+    /// Adds a rule whose replacement is known to be shorter than its window.
+    fn insert_unchecked(&mut self, window: Vec<Instruction>, replacement: Vec<Instruction>) {
+        self.max_window = self.max_window.max(window.len());
+        self.rules.insert(window, replacement);
+    }
+
+    /// Whether any part of the window can be rewritten by a rule in the database.
+    fn contains_rule_for(&self, window: &[Instruction]) -> bool {
+        (1..=window.len().min(self.max_window)).any(|size| {
+            window
+                .windows(size)
+                .any(|part| self.rules.contains_key(part))
+        })
+    }
+}
+
+fn parse_sequence(text: &str) -> anyhow::Result<Vec<Instruction>> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|ins| !ins.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+fn format_sequence(instructions: &[Instruction]) -> String {
+    instructions
+        .iter()
+        .map(|ins| ins.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequence(text: &str) -> Vec<Instruction> {
+        parse_sequence(text).unwrap()
+    }
+
+    fn program(text: &str) -> Program {
+        Program {
+            instructions: sequence(text),
+        }
+    }
+
+    fn database(rules: &[(&str, &str)]) -> RuleDatabase {
+        let mut database = RuleDatabase::new();
+
+        for (window, replacement) in rules {
+            database
+                .insert(sequence(window), sequence(replacement))
+                .unwrap();
+        }
+
+        database
+    }
+
+    #[test]
+    fn insert_rejects_rules_which_dont_shrink() {
+        let mut database = RuleDatabase::new();
+
+        assert!(database
+            .insert(sequence("LOAD 1"), sequence("LOAD 2"))
+            .is_err());
+        assert!(database
+            .insert(sequence("LOAD 1"), sequence("LOAD 2, LOAD 3"))
+            .is_err());
+        assert!(database.is_empty());
+
+        assert!(database
+            .insert(sequence("LOAD 1, LOAD 2"), sequence("LOAD 2"))
+            .is_ok());
+        assert_eq!(database.len(), 1);
+    }
+
+    #[test]
+    fn matches_whole_windows_only() {
+        let database = database(&[("SWAP 0 1, SWAP 1 0", "")]);
+
+        assert_eq!(database.get(&sequence("SWAP 0 1, SWAP 1 0")), Some(&vec![]));
+        assert_eq!(database.get(&sequence("SWAP 0 1")), None);
+        assert_eq!(database.get(&sequence("SWAP 0 1, SWAP 1 0, INC 0")), None);
+
+        assert!(database.contains_rule_for(&sequence("INC 0, SWAP 0 1, SWAP 1 0, INC 0")));
+        assert!(!database.contains_rule_for(&sequence("SWAP 0 1, INC 0, SWAP 1 0")));
+    }
+
+    #[test]
+    fn apply_prefers_the_longest_window() {
+        let database = database(&[
+            ("LOAD 1, LOAD 2", "LOAD 2"),
+            ("LOAD 1, LOAD 2, SWAP 0 1", "LOAD 1, SWAP 0 1"),
+        ]);
+
+        assert_eq!(
+            database.apply(&program("LOAD 1, LOAD 2, SWAP 0 1")),
+            program("LOAD 1, SWAP 0 1")
+        );
+        assert_eq!(
+            database.apply(&program("LOAD 1, LOAD 2, INC 0")),
+            program("LOAD 2, INC 0")
+        );
+    }
+
+    #[test]
+    fn apply_rewrites_until_nothing_matches() {
+        let database = database(&[("INC 0, DECR 0", "")]);
+
+        // removing the inner pair exposes the outer one
+        assert_eq!(
+            database.apply(&program("LOAD 3, INC 0, INC 0, DECR 0, DECR 0, LOAD 4")),
+            program("LOAD 3, LOAD 4")
+        );
+    }
+
+    #[test]
+    fn apply_leaves_programs_with_jumps_alone() {
+        let database = database(&[("INC 0, DECR 0", "")]);
+        let input = program("INC 0, DECR 0, JMP 0");
+
+        assert_eq!(database.apply(&input), input);
+    }
+
+    #[test]
+    fn save_load_round_trip() {
+        let database = database(&[
+            ("SWAP 0 1, SWAP 1 0", ""),
+            ("LOAD 3, LOAD 2", "LOAD 2"),
+            ("INC 4, INC 4, DECR 4", "INC 4"),
+        ]);
+
+        let path = std::env::temp_dir().join(format!("superr-rules-{}.txt", std::process::id()));
+
+        database.save(&path).unwrap();
+        let loaded = RuleDatabase::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.rules, database.rules);
+        assert_eq!(loaded.max_window, database.max_window);
+    }
+
+    #[test]
+    fn load_rejects_rules_which_dont_shrink() {
+        let path =
+            std::env::temp_dir().join(format!("superr-bad-rules-{}.txt", std::process::id()));
+
+        fs::write(&path, "; comment\n\nLOAD 1 => LOAD 2\n").unwrap();
+        let loaded = RuleDatabase::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(loaded.is_err());
+    }
+}
@@ -0,0 +1,37 @@
+use superr_vm::{
+    program::Program,
+    vm::{State, VMConfig},
+};
+
+/// Summary of a finished optimization, for handing over to other tools.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OptimizationResult {
+    /// The VM the programs were optimized for.
+    pub vm: VMConfig,
+
+    /// Name of the optimizer which was used.
+    pub optimizer: String,
+
+    /// Name of the cost model which was minimized.
+    pub cost_model: String,
+
+    /// The state both programs reach.
+    pub target: State,
+
+    pub input: Program,
+    pub input_cost: u64,
+
+    pub output: Program,
+    pub output_cost: u64,
+
+    /// Whether no cheaper program than the output exists, among the programs the optimizer
+    /// could generate.
+    pub proven_optimal: bool,
+
+    /// Amount of programs the optimizer tested.
+    pub programs_tested: u64,
+
+    /// How long optimizing took, in seconds.
+    pub elapsed: f64,
+}
@@ -0,0 +1,228 @@
+use std::ops::RangeInclusive;
+
+use superr_vm::{
+    address::MemoryAddress,
+    analysis::CellSet,
+    instruction::{Instruction, Opcode},
+    program::Program,
+    vm::{MemValue, MEM_SIZE},
+};
+
+/// Opcodes the optimizers use unless told otherwise.
+///
+/// PUT and JMP are left out, as we only ever optimize for the final state of the memory.
+pub const DEFAULT_OPCODES: [Opcode; 7] = [
+    Opcode::Load,
+    Opcode::Swap,
+    Opcode::XOR,
+    Opcode::Inc,
+    Opcode::Decr,
+    Opcode::Add,
+    Opcode::Sub,
+];
+
+/// The instructions a program may be made of: which opcodes are allowed, which values LOAD
+/// may take and which memory cells may be used.
+///
+/// Restricting the memory cells shrinks the space a lot: every instruction with two operands
+/// has `n * n` variants for `n` cells. As LOAD always writes to cell 0, it's only part of the
+/// space if cell 0 is allowed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstructionSpace {
+    /// Allowed opcodes. JMP is never generated, even if it's listed.
+    pub opcodes: Vec<Opcode>,
+
+    /// Values LOAD may take.
+    pub imm: RangeInclusive<MemValue>,
+
+    /// Memory cells instructions may use.
+    pub addresses: CellSet,
+}
+
+impl InstructionSpace {
+    /// Every opcode in [`DEFAULT_OPCODES`], with LOAD values of up to `max_imm` and every
+    /// memory cell.
+    pub fn new(max_imm: MemValue) -> Self {
+        Self {
+            opcodes: DEFAULT_OPCODES.to_vec(),
+            imm: 0..=max_imm,
+            addresses: CellSet::ALL,
+        }
+    }
+
+    pub fn with_opcodes(mut self, opcodes: Vec<Opcode>) -> Self {
+        self.opcodes = opcodes;
+        self
+    }
+
+    /// Disallows the given opcode.
+    pub fn without(mut self, opcode: Opcode) -> Self {
+        self.opcodes.retain(|&allowed| allowed != opcode);
+        self
+    }
+
+    pub fn with_imm(mut self, imm: RangeInclusive<MemValue>) -> Self {
+        self.imm = imm;
+        self
+    }
+
+    pub fn with_addresses(mut self, addresses: CellSet) -> Self {
+        self.addresses = addresses;
+        self
+    }
+
+    /// Whether no instruction at all can be generated.
+    pub fn is_empty(&self) -> bool {
+        self.instructions().is_empty()
+    }
+
+    /// Whether the instruction is part of the space.
+    pub fn contains(&self, instruction: &Instruction) -> bool {
+        let address = |&addr: &MemoryAddress| self.addresses.contains(addr);
+
+        self.opcodes.contains(&instruction.opcode())
+            && match instruction {
+                Instruction::Load(val) => self.imm.contains(val) && address(&0),
+
+                Instruction::Swap(a, b)
+                | Instruction::XOR(a, b)
+                | Instruction::Add(a, b)
+                | Instruction::Sub(a, b) => address(a) && address(b),
+
+                Instruction::Inc(addr) | Instruction::Decr(addr) | Instruction::Put(addr) => {
+                    address(addr)
+                }
+
+                Instruction::Jmp(_) => false,
+            }
+    }
+
+    /// Whether every instruction of the program is part of the space.
+    pub fn contains_program(&self, program: &Program) -> bool {
+        program.instructions.iter().all(|ins| self.contains(ins))
+    }
+
+    /// Lists every instruction in the space, grouped by opcode in the order of
+    /// [`InstructionSpace::opcodes`].
+    pub fn instructions(&self) -> Vec<Instruction> {
+        let mut instructions = vec![];
+
+        for &opcode in &self.opcodes {
+            match opcode {
+                Opcode::Load if self.addresses.contains(0) => {
+                    instructions.extend(self.imm.clone().map(Instruction::Load))
+                }
+
+                Opcode::Swap | Opcode::XOR | Opcode::Add | Opcode::Sub => {
+                    for a in self.addresses.iter() {
+                        for b in self.addresses.iter() {
+                            instructions.push(Self::create_instruction(opcode, 0, a, b));
+                        }
+                    }
+                }
+
+                Opcode::Inc | Opcode::Decr | Opcode::Put => instructions.extend(
+                    self.addresses
+                        .iter()
+                        .map(|addr| Self::create_instruction(opcode, 0, addr, 0)),
+                ),
+
+                Opcode::Load | Opcode::Jmp => {}
+            }
+        }
+
+        instructions
+    }
+
+    /// Randomly generates a single instruction from the space.
+    ///
+    /// Every allowed opcode is equally likely, and so are its operands.
+    pub fn generate(&self) -> Instruction {
+        let generatable = |opcode: Opcode| match opcode {
+            Opcode::Load => self.addresses.contains(0),
+            Opcode::Jmp => false,
+
+            _ => !self.addresses.is_empty(),
+        };
+
+        assert!(
+            self.opcodes.iter().any(|&opcode| generatable(opcode)),
+            "instruction space is empty"
+        );
+
+        // this is called for every instruction of every random program, so we pick until we
+        // get something usable rather than collecting the usable opcodes first
+        let opcode = loop {
+            let opcode = self.opcodes[fastrand::usize(0..self.opcodes.len())];
+
+            if generatable(opcode) {
+                break opcode;
+            }
+        };
+
+        let address = || {
+            self.addresses
+                .iter()
+                .nth(fastrand::usize(0..self.addresses.len().max(1)))
+                .unwrap_or(0)
+        };
+
+        let imm = fastrand::u8(self.imm.clone());
+        let a = address();
+        let b = address();
+
+        Self::create_instruction(opcode, imm, a, b)
+    }
+
+    fn create_instruction(
+        opcode: Opcode,
+        imm: MemValue,
+        a: MemoryAddress,
+        b: MemoryAddress,
+    ) -> Instruction {
+        match opcode {
+            Opcode::Load => Instruction::Load(imm),
+
+            Opcode::Swap => Instruction::Swap(a, b),
+            Opcode::XOR => Instruction::XOR(a, b),
+
+            Opcode::Inc => Instruction::Inc(a),
+            Opcode::Decr => Instruction::Decr(a),
+
+            Opcode::Add => Instruction::Add(a, b),
+            Opcode::Sub => Instruction::Sub(a, b),
+
+            Opcode::Put => Instruction::Put(a),
+
+            Opcode::Jmp => panic!("JMP can't be generated"),
+        }
+    }
+}
+
+/// Parses a list of memory cells such as `0-3,7`, where each comma separated item is either a
+/// single cell or an inclusive range of cells.
+pub fn parse_addresses(text: &str) -> Result<CellSet, String> {
+    let mut addresses = CellSet::EMPTY;
+
+    for item in text.split(',').map(str::trim) {
+        let (first, last) = item.split_once('-').unwrap_or((item, item));
+
+        let parse = |addr: &str| {
+            addr.trim()
+                .parse::<MemoryAddress>()
+                .ok()
+                .filter(|&addr| addr < MEM_SIZE)
+                .ok_or_else(|| format!("invalid address: {}", addr))
+        };
+
+        for addr in parse(first)?..=parse(last)? {
+            addresses.insert(addr);
+        }
+    }
+
+    if addresses.is_empty() {
+        return Err("no addresses given".to_string());
+    }
+
+    Ok(addresses)
+}
@@ -1,58 +1,44 @@
-use std::{
-    collections::VecDeque,
-    mem,
-    sync::{Arc, Mutex},
-};
+use std::cell::RefCell;
 
-use superr_vm::vm::VM;
+use superr_vm::vm::{State, VM};
 
-// A thread-safe pool of VMs
-pub struct VMPool {
-    vms: Arc<Mutex<VecDeque<VM>>>,
-}
+/// Everything needed to evaluate candidate programs, kept around so that evaluating one doesn't
+/// allocate.
+#[derive(Debug, Default)]
+pub struct Scratch {
+    pub vm: VM,
 
-impl VMPool {
-    pub fn new(capacity: usize) -> Self {
-        let mut vms = VecDeque::with_capacity(capacity);
-
-        for _ in 0..capacity {
-            vms.push_back(VM::default());
-            dbg!(vms.len());
-        }
-
-        Self {
-            vms: Arc::new(Mutex::new(vms)),
-        }
-    }
-
-    // Get a VM from the pool, or create a new one if none are available
-    pub fn get(&self) -> VMHandle {
-        let mut pool = self.vms.lock().unwrap();
-        let vm = pool.pop_front().unwrap_or_else(|| VM::default());
-
-        VMHandle {
-            vm,
-            pool: Arc::clone(&self.vms),
-        }
-    }
+    /// Buffer for the resulting states of running a candidate on test vectors, see
+    /// [`crate::equivalence::TestVectors::fingerprint_into`].
+    pub states: Vec<State>,
 }
 
-// RAII handle for a VM that returns it to the pool when dropped
-pub struct VMHandle {
-    vm: VM,
-    pool: Arc<Mutex<VecDeque<VM>>>,
+thread_local! {
+    static SCRATCH: RefCell<Scratch> = RefCell::new(Scratch::default());
 }
 
-impl VMHandle {
-    pub fn get_mut(&mut self) -> &mut VM {
-        &mut self.vm
-    }
+/// Runs `f` with this thread's [`Scratch`].
+///
+/// Every thread has its own, so unlike a shared pool there's no locking, and as the optimizers
+/// run on a fixed set of rayon threads the same few are reused for the whole search. The VM is
+/// reset beforehand, but the buffers are left as they are.
+///
+/// # Panics
+///
+/// If called from within `f`, as the scratch is already in use.
+pub fn with_scratch<R>(f: impl FnOnce(&mut Scratch) -> R) -> R {
+    SCRATCH.with(|scratch| {
+        let mut scratch = scratch
+            .try_borrow_mut()
+            .expect("scratch is already in use on this thread");
+
+        scratch.vm.reset();
+
+        f(&mut scratch)
+    })
 }
 
-impl Drop for VMHandle {
-    fn drop(&mut self) {
-        self.vm.reset(); // Reset the VM state before returning to pool
-        let mut pool = self.pool.lock().unwrap();
-        pool.push_back(mem::replace(&mut self.vm, VM::default()));
-    }
+/// Runs `f` with this thread's VM, which is reset beforehand. See [`with_scratch`].
+pub fn with_vm<R>(f: impl FnOnce(&mut VM) -> R) -> R {
+    with_scratch(|scratch| f(&mut scratch.vm))
 }
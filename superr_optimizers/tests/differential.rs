@@ -0,0 +1,144 @@
+use superr_optimizers::space::InstructionSpace;
+use superr_vm::{
+    batch::{Batch, ALL_LANES, LANES},
+    compiled::CompiledProgram,
+    instruction::{Instruction, Opcode},
+    program::Program,
+    vm::{MemValue, State, MEM_SIZE, VM},
+};
+
+const PROGRAMS: usize = 2_000;
+
+/// Every instruction which only touches memory, with any operands. PUT would print, and JMP is
+/// added separately as it can loop forever.
+fn space() -> InstructionSpace {
+    InstructionSpace::new(MemValue::MAX).with_opcodes(
+        Opcode::ALL
+            .into_iter()
+            .filter(|&opcode| opcode != Opcode::Put && opcode != Opcode::Jmp)
+            .collect(),
+    )
+}
+
+/// Random programs of up to 16 instructions. With `jumps`, some of the instructions are JMPs,
+/// which only ever jump forward (possibly past the end) so that every program terminates.
+fn programs(seed: u64, jumps: bool) -> impl Iterator<Item = Program> {
+    fastrand::seed(seed);
+
+    let space = space();
+
+    (0..PROGRAMS).map(move |_| {
+        let length = fastrand::usize(0..=16);
+
+        let instructions = (0..length)
+            .map(|i| {
+                if jumps && fastrand::u8(..4) == 0 {
+                    Instruction::Jmp(fastrand::usize(i + 1..=length + 1))
+                } else {
+                    space.generate()
+                }
+            })
+            .collect();
+
+        Program { instructions }
+    })
+}
+
+/// A random state. Values are drawn from a few small numbers half of the time, so that lanes
+/// end up with the same state often enough for matching to be tested.
+fn state() -> State {
+    if fastrand::bool() {
+        [(); MEM_SIZE].map(|_| fastrand::u8(0..2))
+    } else {
+        [(); MEM_SIZE].map(|_| fastrand::u8(..))
+    }
+}
+
+/// Runs the program on the VM, starting from the given state.
+fn run(program: &Program, state: &State) -> State {
+    let mut vm = VM {
+        state: *state,
+        pc: 0,
+    };
+
+    vm.execute(&program.instructions);
+
+    vm.state
+}
+
+#[test]
+fn batch_matches_vm() {
+    for program in programs(0, true) {
+        // partial batches leave some lanes zeroed, which have to be run too
+        let lanes = fastrand::usize(1..=LANES);
+        let states = (0..lanes).map(|_| state()).collect::<Vec<State>>();
+
+        let mut batch = Batch::from_states(&states);
+        batch.execute(&program.instructions);
+
+        for lane in 0..LANES {
+            let expected = run(&program, states.get(lane).unwrap_or(&[0; MEM_SIZE]));
+
+            assert_eq!(batch.state(lane), expected, "lane {} of {}", lane, program);
+        }
+    }
+}
+
+#[test]
+fn batch_matching_matches_vm() {
+    for program in programs(1, true) {
+        let lanes = fastrand::usize(1..=LANES);
+        let states = (0..lanes).map(|_| state()).collect::<Vec<State>>();
+
+        let mut batch = Batch::from_states(&states);
+        batch.execute(&program.instructions);
+
+        let results = states
+            .iter()
+            .map(|state| run(&program, state))
+            .collect::<Vec<State>>();
+
+        // only the lanes in use are looked at, as the exhaustive optimizer does
+        let used = ALL_LANES >> (LANES - lanes);
+
+        for target in &results {
+            let expected = results
+                .iter()
+                .enumerate()
+                .filter(|(_, result)| *result == target)
+                .fold(0, |mask, (lane, _)| mask | 1 << lane);
+
+            assert_eq!(batch.matching(target) & used, expected, "{}", program);
+        }
+    }
+}
+
+/// Compiled programs without JMP are run straight through, with their SWAPs resolved while
+/// compiling, so they're checked separately from the ones with a program counter.
+#[test]
+fn compiled_matches_vm() {
+    for program in programs(2, false) {
+        let compiled = CompiledProgram::from(&program);
+        let start = state();
+
+        let mut state = start;
+        compiled.execute(&mut state);
+
+        assert_eq!(state, run(&program, &start), "{}", program);
+        assert_eq!(compiled.compute_state(), VM::compute_state(&program));
+    }
+}
+
+#[test]
+fn compiled_with_jumps_matches_vm() {
+    for program in programs(3, true) {
+        let compiled = CompiledProgram::from(&program);
+        let start = state();
+
+        let mut state = start;
+        compiled.execute(&mut state);
+
+        assert_eq!(state, run(&program, &start), "{}", program);
+        assert_eq!(compiled.compute_state(), VM::compute_state(&program));
+    }
+}
@@ -0,0 +1,58 @@
+use superr_optimizers::space::InstructionSpace;
+use superr_vm::{
+    instruction::{Instruction, Opcode},
+    program::Program,
+    vm::MemValue,
+};
+
+const PROGRAMS: usize = 10_000;
+
+/// Random programs using every instruction, with any operands, including JMP which the
+/// instruction space never generates.
+fn programs() -> impl Iterator<Item = Program> {
+    fastrand::seed(0);
+
+    let space = InstructionSpace::new(MemValue::MAX).with_opcodes(
+        Opcode::ALL
+            .into_iter()
+            .filter(|&opcode| opcode != Opcode::Jmp)
+            .collect(),
+    );
+
+    (0..PROGRAMS).map(move |_| {
+        let instructions = (0..fastrand::usize(0..=16))
+            .map(|_| {
+                if fastrand::u8(..10) == 0 {
+                    Instruction::Jmp(fastrand::u32(..) as usize)
+                } else {
+                    space.generate()
+                }
+            })
+            .collect();
+
+        Program { instructions }
+    })
+}
+
+#[test]
+fn assembly_round_trip() {
+    for program in programs() {
+        let text = program.to_string();
+
+        assert_eq!(text.parse::<Program>().unwrap(), program, "{}", text);
+
+        for instruction in &program.instructions {
+            assert_eq!(
+                instruction.to_string().parse::<Instruction>().unwrap(),
+                *instruction
+            );
+        }
+    }
+}
+
+#[test]
+fn encoding_round_trip() {
+    for program in programs() {
+        assert_eq!(Program::decode(&program.encode()).unwrap(), program);
+    }
+}
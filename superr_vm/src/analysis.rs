@@ -0,0 +1,456 @@
+use crate::{
+    address::MemoryAddress,
+    instruction::Instruction,
+    program::Program,
+    vm::{MemValue, MEM_SIZE},
+};
+
+// All of the analyses in this module assume straight-line code: jumps are not followed. Programs
+// containing a JMP are left untouched by [`simplify`].
+
+/// A set of memory cells, stored as a bitmask.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct CellSet(u32);
+
+impl CellSet {
+    pub const EMPTY: CellSet = CellSet(0);
+    pub const ALL: CellSet = CellSet((1 << MEM_SIZE) - 1);
+
+    pub fn single(addr: MemoryAddress) -> Self {
+        CellSet(1 << addr)
+    }
+
+    pub fn insert(&mut self, addr: MemoryAddress) {
+        self.0 |= 1 << addr;
+    }
+
+    pub fn contains(&self, addr: MemoryAddress) -> bool {
+        self.0 & (1 << addr) != 0
+    }
+
+    pub fn union(self, other: CellSet) -> CellSet {
+        CellSet(self.0 | other.0)
+    }
+
+    pub fn difference(self, other: CellSet) -> CellSet {
+        CellSet(self.0 & !other.0)
+    }
+
+    pub fn intersects(self, other: CellSet) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn len(self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = MemoryAddress> {
+        (0..MEM_SIZE).filter(move |&addr| self.contains(addr))
+    }
+}
+
+/// The cells an instruction writes to (`defs`) and reads from (`uses`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DefUse {
+    pub defs: CellSet,
+    pub uses: CellSet,
+}
+
+/// Computes the def/use sets of a single instruction.
+///
+/// Instructions whose result doesn't depend on their operands (`XOR a a`, `SUB a a`) don't
+/// use anything, and `SWAP a a` neither defines nor uses anything.
+pub fn def_use(instruction: &Instruction) -> DefUse {
+    let (defs, uses) = match *instruction {
+        Instruction::Load(_) => (CellSet::single(0), CellSet::EMPTY),
+
+        Instruction::Swap(a, b) if a == b => (CellSet::EMPTY, CellSet::EMPTY),
+        Instruction::Swap(a, b) => {
+            let cells = CellSet::single(a).union(CellSet::single(b));
+
+            (cells, cells)
+        }
+
+        Instruction::XOR(a, b) | Instruction::Sub(a, b) if a == b => {
+            (CellSet::single(a), CellSet::EMPTY)
+        }
+        Instruction::XOR(a, b) | Instruction::Add(a, b) | Instruction::Sub(a, b) => (
+            CellSet::single(a),
+            CellSet::single(a).union(CellSet::single(b)),
+        ),
+
+        Instruction::Inc(a) | Instruction::Decr(a) => (CellSet::single(a), CellSet::single(a)),
+
+        Instruction::Put(a) => (CellSet::EMPTY, CellSet::single(a)),
+
+        Instruction::Jmp(_) => (CellSet::EMPTY, CellSet::EMPTY),
+    };
+
+    DefUse { defs, uses }
+}
+
+/// The cells which any of the instructions read from or write to.
+pub fn cells_used(instructions: &[Instruction]) -> CellSet {
+    instructions
+        .iter()
+        .map(def_use)
+        .fold(CellSet::EMPTY, |cells, du| {
+            cells.union(du.defs).union(du.uses)
+        })
+}
+
+/// Whether the instruction does anything other than modifying memory.
+pub fn has_side_effects(instruction: &Instruction) -> bool {
+    matches!(instruction, Instruction::Put(_) | Instruction::Jmp(_))
+}
+
+/// Computes the live cells before every instruction of the program.
+///
+/// The returned vector has one more element than there are instructions: element `i` holds the
+/// cells live right before instruction `i`, and the last element is `live_out` itself. When
+/// optimizing towards a target state every cell is observable, so `live_out` is usually
+/// [`CellSet::ALL`].
+pub fn liveness(instructions: &[Instruction], live_out: CellSet) -> Vec<CellSet> {
+    let mut live = vec![CellSet::EMPTY; instructions.len() + 1];
+    live[instructions.len()] = live_out;
+
+    for (i, instruction) in instructions.iter().enumerate().rev() {
+        let DefUse { defs, uses } = def_use(instruction);
+
+        live[i] = live[i + 1].difference(defs).union(uses);
+    }
+
+    live
+}
+
+/// What we statically know about each memory cell: `Some` if its value is a known constant.
+pub type ConstState = [Option<MemValue>; MEM_SIZE];
+
+/// The abstract state of a freshly reset VM, where every cell is known to be zero.
+pub const ZERO_STATE: ConstState = [Some(0); MEM_SIZE];
+
+/// Applies a single instruction to an abstract state.
+pub fn eval_const(state: &mut ConstState, instruction: &Instruction) {
+    match *instruction {
+        Instruction::Load(val) => state[0] = Some(val),
+
+        Instruction::Swap(a, b) => state.swap(a, b),
+
+        Instruction::XOR(a, b) | Instruction::Sub(a, b) if a == b => state[a] = Some(0),
+
+        Instruction::XOR(a, b) => state[a] = state[a].zip(state[b]).map(|(a, b)| a ^ b),
+
+        Instruction::Add(a, b) => state[a] = state[a].zip(state[b]).map(|(a, b)| a.wrapping_add(b)),
+
+        Instruction::Sub(a, b) => state[a] = state[a].zip(state[b]).map(|(a, b)| a.wrapping_sub(b)),
+
+        Instruction::Inc(a) => state[a] = state[a].map(|a| a.wrapping_add(1)),
+        Instruction::Decr(a) => state[a] = state[a].map(|a| a.wrapping_sub(1)),
+
+        Instruction::Put(_) | Instruction::Jmp(_) => {}
+    }
+}
+
+/// Propagates constants forwards through the program, starting from `entry`.
+///
+/// Like [`liveness`], the returned vector has one more element than there are instructions:
+/// element `i` is the abstract state right before instruction `i`, and the last element is the
+/// state at the end of the program.
+pub fn constants(instructions: &[Instruction], entry: ConstState) -> Vec<ConstState> {
+    let mut states = Vec::with_capacity(instructions.len() + 1);
+    let mut state = entry;
+
+    states.push(state);
+
+    for instruction in instructions {
+        eval_const(&mut state, instruction);
+        states.push(state);
+    }
+
+    states
+}
+
+/// Cheap, deterministic simplification pass.
+///
+/// Repeatedly removes dead and redundant writes, folds chains of INC/DECR on the same cell and
+/// collapses pairs of identical SWAPs, until nothing changes. The result computes the same final
+/// state as the input (when run on a freshly reset VM) and only ever has instructions removed,
+/// which makes it a good starting upper bound for the search based optimizers.
+pub fn simplify(program: &Program) -> Program {
+    let mut instructions = program.instructions.clone();
+
+    if instructions
+        .iter()
+        .any(|ins| matches!(ins, Instruction::Jmp(_)))
+    {
+        return program.clone();
+    }
+
+    loop {
+        let mut changed = false;
+
+        changed |= remove_dead_writes(&mut instructions);
+        changed |= remove_redundant_writes(&mut instructions);
+        changed |= fold_inc_decr(&mut instructions);
+        changed |= collapse_swaps(&mut instructions);
+
+        if !changed {
+            break;
+        }
+    }
+
+    Program { instructions }
+}
+
+/// Removes instructions whose writes are all overwritten before being read.
+fn remove_dead_writes(instructions: &mut Vec<Instruction>) -> bool {
+    let live = liveness(instructions, CellSet::ALL);
+    let len = instructions.len();
+
+    let mut i = 0;
+
+    instructions.retain(|instruction| {
+        let live_after = live[i + 1];
+        i += 1;
+
+        has_side_effects(instruction) || def_use(instruction).defs.intersects(live_after)
+    });
+
+    instructions.len() != len
+}
+
+/// Removes instructions which write values that are already known to be in memory.
+fn remove_redundant_writes(instructions: &mut Vec<Instruction>) -> bool {
+    let states = constants(instructions, ZERO_STATE);
+    let len = instructions.len();
+
+    let mut i = 0;
+
+    instructions.retain(|instruction| {
+        let (before, after) = (&states[i], &states[i + 1]);
+        i += 1;
+
+        has_side_effects(instruction)
+            || def_use(instruction)
+                .defs
+                .iter()
+                .any(|addr| before[addr].is_none() || before[addr] != after[addr])
+    });
+
+    instructions.len() != len
+}
+
+/// Replaces runs of INC/DECR on the same cell by the shortest equivalent run.
+///
+/// The replacement only ever drops instructions from the run, rather than switching between
+/// INC and DECR: which of the two is cheaper is up to the cost model, so a run of 130 INCs is
+/// left alone even though 126 DECRs would be shorter.
+fn fold_inc_decr(instructions: &mut Vec<Instruction>) -> bool {
+    let mut folded = Vec::with_capacity(instructions.len());
+    let mut changed = false;
+
+    let mut i = 0;
+
+    while i < instructions.len() {
+        let addr = match instructions[i] {
+            Instruction::Inc(addr) | Instruction::Decr(addr) => addr,
+
+            instruction => {
+                folded.push(instruction);
+                i += 1;
+
+                continue;
+            }
+        };
+
+        // find the end of the run, and how many of each instruction it has
+        let (mut incs, mut decrs) = (0, 0);
+        let start = i;
+
+        while i < instructions.len() {
+            match instructions[i] {
+                Instruction::Inc(a) if a == addr => incs += 1,
+                Instruction::Decr(a) if a == addr => decrs += 1,
+
+                _ => break,
+            }
+
+            i += 1;
+        }
+
+        // values wrap around, so the net amount is taken modulo the amount of values
+        let net = (incs as MemValue).wrapping_sub(decrs as MemValue);
+
+        // at least one of these always fits, as the net amount is never more than the
+        // instructions going in its direction
+        let replacement = [
+            (net as usize <= incs).then(|| vec![Instruction::Inc(addr); net as usize]),
+            (net.wrapping_neg() as usize <= decrs)
+                .then(|| vec![Instruction::Decr(addr); net.wrapping_neg() as usize]),
+        ]
+        .into_iter()
+        .flatten()
+        .min_by_key(Vec::len)
+        .unwrap();
+
+        changed |= replacement.len() < i - start;
+        folded.extend(replacement);
+    }
+
+    *instructions = folded;
+
+    changed
+}
+
+/// Removes pairs of consecutive SWAPs of the same two cells, which cancel each other out.
+fn collapse_swaps(instructions: &mut Vec<Instruction>) -> bool {
+    let mut collapsed: Vec<Instruction> = Vec::with_capacity(instructions.len());
+
+    for &instruction in instructions.iter() {
+        match (collapsed.last(), instruction) {
+            (Some(&Instruction::Swap(a, b)), Instruction::Swap(c, d))
+                if (a, b) == (c, d) || (a, b) == (d, c) =>
+            {
+                collapsed.pop();
+            }
+
+            _ => collapsed.push(instruction),
+        }
+    }
+
+    let changed = collapsed.len() != instructions.len();
+    *instructions = collapsed;
+
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::VM;
+
+    use super::*;
+
+    fn program(source: &str) -> Program {
+        source.parse().unwrap()
+    }
+
+    #[test]
+    fn liveness_tracks_reads_and_overwrites() {
+        let instructions = program("LOAD 1\nSWAP 0 1\nLOAD 2\nADD 0 1").instructions;
+        let live = liveness(&instructions, CellSet::single(0));
+
+        assert_eq!(live.len(), instructions.len() + 1);
+        assert_eq!(live[4], CellSet::single(0));
+
+        // ADD reads both of its operands
+        assert_eq!(live[3], CellSet::single(0).union(CellSet::single(1)));
+
+        // cell 0 is overwritten by LOAD before being read
+        assert_eq!(live[2], CellSet::single(1));
+        assert_eq!(live[1], CellSet::single(0).union(CellSet::single(1)));
+        assert_eq!(live[0], CellSet::single(1));
+    }
+
+    #[test]
+    fn liveness_ignores_self_cancelling_instructions() {
+        let instructions = program("XOR 2 2\nSWAP 3 3").instructions;
+        let live = liveness(&instructions, CellSet::ALL);
+
+        assert!(!live[0].contains(2));
+        assert!(live[0].contains(3));
+    }
+
+    #[test]
+    fn constants_are_propagated() {
+        let instructions = program("LOAD 5\nSWAP 0 1\nINC 1\nDECR 2\nADD 1 2").instructions;
+        let states = constants(&instructions, ZERO_STATE);
+
+        assert_eq!(states.len(), instructions.len() + 1);
+        assert_eq!(states[0], ZERO_STATE);
+
+        let end = states[5];
+
+        assert_eq!(end[0], Some(0));
+        assert_eq!(end[1], Some(5));
+        assert_eq!(end[2], Some(255));
+    }
+
+    #[test]
+    fn constants_unknown_values_spread() {
+        let mut entry = ZERO_STATE;
+        entry[1] = None;
+
+        let instructions = program("ADD 0 1\nXOR 1 1").instructions;
+        let end = constants(&instructions, entry)[2];
+
+        assert_eq!(end[0], None);
+
+        // XOR of a cell with itself is always 0
+        assert_eq!(end[1], Some(0));
+    }
+
+    #[test]
+    fn simplify_removes_dead_writes() {
+        let simplified = simplify(&program("LOAD 1\nLOAD 2\nSWAP 0 1\nLOAD 3\nLOAD 3"));
+
+        assert_eq!(simplified, program("LOAD 2\nSWAP 0 1\nLOAD 3"));
+    }
+
+    #[test]
+    fn simplify_keeps_side_effects() {
+        let input = program("LOAD 1\nPUT 0\nLOAD 2");
+
+        assert_eq!(simplify(&input), input);
+    }
+
+    #[test]
+    fn simplify_leaves_jumps_alone() {
+        let input = program("LOAD 1\nLOAD 1\nJMP 0");
+
+        assert_eq!(simplify(&input), input);
+    }
+
+    #[test]
+    fn simplify_folds_inc_decr() {
+        assert_eq!(
+            simplify(&program("INC 1\nINC 1\nDECR 1\nINC 1")),
+            program("INC 1\nINC 1")
+        );
+
+        // wraps around, and goes whichever way is shorter when both are in the run
+        let mut source = vec!["DECR 1"; 3];
+        source.extend(["INC 1"; 257]);
+
+        assert_eq!(
+            simplify(&program(&source.join("\n"))),
+            program("DECR 1\nDECR 1")
+        );
+    }
+
+    #[test]
+    fn simplify_never_changes_direction() {
+        let input = program(&["INC 1"; 130].join("\n"));
+
+        assert_eq!(simplify(&input), input);
+    }
+
+    #[test]
+    fn simplify_preserves_the_final_state() {
+        let inputs = [
+            "LOAD 3\nSWAP 0 1\nSWAP 1 0\nINC 0\nDECR 0\nXOR 2 2\nLOAD 3",
+            "LOAD 7\nSWAP 0 4\nLOAD 7\nADD 4 0\nSUB 0 0\nINC 4\nINC 4\nDECR 4",
+            "INC 0\nSWAP 0 1\nSWAP 0 1\nSWAP 0 1\nLOAD 0\nLOAD 0",
+        ];
+
+        for input in inputs.map(program) {
+            let simplified = simplify(&input);
+
+            assert!(simplified.instructions.len() <= input.instructions.len());
+            assert_eq!(VM::compute_state(&simplified), VM::compute_state(&input));
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{
+    instruction::Instruction,
+    vm::{MemValue, State, MEM_SIZE},
+};
+
+/// Amount of states in a [`Batch`].
+pub const LANES: usize = 32;
+
+/// A bitmask with one bit per lane of a [`Batch`].
+pub type LaneMask = u32;
+
+/// Mask with every lane set.
+pub const ALL_LANES: LaneMask = LaneMask::MAX;
+
+/// Many states which are run in lockstep, all executing the same instructions.
+///
+/// The states are stored by cell rather than by state, so that running an instruction is the
+/// same operation on a few rows of [`LANES`] values, which the compiler turns into SIMD
+/// instructions.
+///
+/// Unlike [`crate::vm::VM`], PUT doesn't print anything. As JMP is unconditional, every lane
+/// always follows the same path, so it's supported as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Batch {
+    /// `cells[c][l]` is the value of cell `c` in lane `l`.
+    pub cells: [[MemValue; LANES]; MEM_SIZE],
+}
+
+impl Default for Batch {
+    fn default() -> Self {
+        Self {
+            cells: [[0; LANES]; MEM_SIZE],
+        }
+    }
+}
+
+impl Batch {
+    /// A batch where every lane has the same state.
+    pub fn splat(state: &State) -> Self {
+        let mut batch = Self::default();
+
+        for (row, &value) in batch.cells.iter_mut().zip(state) {
+            *row = [value; LANES];
+        }
+
+        batch
+    }
+
+    /// A batch holding the given states, which must be at most [`LANES`]. Lanes without a
+    /// state are left zeroed.
+    pub fn from_states(states: &[State]) -> Self {
+        assert!(states.len() <= LANES, "too many states for a batch");
+
+        let mut batch = Self::default();
+
+        for (lane, state) in states.iter().enumerate() {
+            batch.set_state(lane, state);
+        }
+
+        batch
+    }
+
+    pub fn state(&self, lane: usize) -> State {
+        let mut state = [0; MEM_SIZE];
+
+        for (value, row) in state.iter_mut().zip(&self.cells) {
+            *value = row[lane];
+        }
+
+        state
+    }
+
+    pub fn set_state(&mut self, lane: usize, state: &State) {
+        for (row, &value) in self.cells.iter_mut().zip(state) {
+            row[lane] = value;
+        }
+    }
+
+    /// Runs the instructions on every lane.
+    #[inline(always)]
+    pub fn execute(&mut self, instructions: &[Instruction]) {
+        let mut pc = 0;
+
+        while pc < instructions.len() {
+            let instruction = instructions[pc];
+
+            pc += 1;
+
+            match instruction {
+                Instruction::Jmp(ins) => pc = ins,
+
+                _ => self.execute_instruction(instruction),
+            }
+        }
+    }
+
+    /// Runs a single instruction on every lane. JMP does nothing, as it's handled by
+    /// [`Batch::execute`].
+    #[inline(always)]
+    pub fn execute_instruction(&mut self, instruction: Instruction) {
+        match instruction {
+            Instruction::Load(val) => self.cells[0] = [val; LANES],
+
+            Instruction::Swap(a, b) => self.cells.swap(a, b),
+
+            Instruction::XOR(a, b) => self.apply(a, b, |x, y| x ^ y),
+
+            Instruction::Inc(addr) => self.apply(addr, addr, |x, _| x.wrapping_add(1)),
+
+            Instruction::Decr(addr) => self.apply(addr, addr, |x, _| x.wrapping_sub(1)),
+
+            Instruction::Add(a, b) => self.apply(a, b, MemValue::wrapping_add),
+
+            Instruction::Sub(a, b) => self.apply(a, b, MemValue::wrapping_sub),
+
+            Instruction::Put(_) | Instruction::Jmp(_) => {}
+        }
+    }
+
+    /// The lanes whose state is equal to the given one.
+    #[inline(always)]
+    pub fn matching(&self, state: &State) -> LaneMask {
+        let mut equal = [true; LANES];
+
+        for (row, &value) in self.cells.iter().zip(state) {
+            for (equal, &cell) in equal.iter_mut().zip(row) {
+                *equal &= cell == value;
+            }
+        }
+
+        equal
+            .iter()
+            .enumerate()
+            .fold(0, |mask, (lane, &equal)| mask | (equal as LaneMask) << lane)
+    }
+
+    /// Sets cell `a` to `op(a, b)` in every lane.
+    #[inline(always)]
+    fn apply(&mut self, a: usize, b: usize, op: impl Fn(MemValue, MemValue) -> MemValue) {
+        let source = self.cells[b];
+
+        for (x, y) in self.cells[a].iter_mut().zip(source) {
+            *x = op(*x, y);
+        }
+    }
+}
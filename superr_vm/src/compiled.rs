@@ -0,0 +1,223 @@
+use crate::{
+    instruction::Instruction,
+    program::Program,
+    vm::{MemValue, State, MEM_SIZE},
+};
+
+/// A single compiled instruction: the function implementing its opcode, along with its
+/// operands. LOAD keeps its value in `a`.
+#[derive(Clone, Copy)]
+struct Op {
+    run: fn(&mut State, usize, usize),
+    a: usize,
+    b: usize,
+}
+
+/// A program compiled into direct-threaded code: a list of functions with their operands
+/// already resolved.
+///
+/// Running it skips the `match` on every instruction [`crate::vm::VM`] has to do, which pays
+/// off when the same program is run on many states. Programs without JMP, which is all of
+/// them as far as the optimizers are concerned, are also run straight through without a
+/// program counter, and their SWAPs are resolved while compiling: rather than moving values
+/// around, the operands of later instructions are renamed, and the cells are put in place once
+/// at the end.
+pub struct CompiledProgram {
+    ops: Vec<Op>,
+
+    /// Target of every JMP, by instruction. Empty if the program has no JMP, in which case
+    /// it's run straight through.
+    jumps: Vec<Option<usize>>,
+
+    /// Which cell holds the value of each cell once the ops have run, if SWAPs were resolved
+    /// while compiling and left the cells out of place.
+    permutation: Option<[usize; MEM_SIZE]>,
+
+    /// Amount of instructions in the program.
+    len: usize,
+}
+
+impl CompiledProgram {
+    pub fn compile(instructions: &[Instruction]) -> Self {
+        let len = instructions.len();
+
+        if instructions
+            .iter()
+            .any(|ins| matches!(ins, Instruction::Jmp(_)))
+        {
+            return Self {
+                ops: instructions.iter().map(|&ins| compile(ins)).collect(),
+                jumps: instructions
+                    .iter()
+                    .map(|ins| match ins {
+                        Instruction::Jmp(target) => Some(*target),
+                        _ => None,
+                    })
+                    .collect(),
+                permutation: None,
+                len,
+            };
+        }
+
+        // cells[i] is the cell currently holding what the program thinks of as cell i
+        let mut cells: [usize; MEM_SIZE] = std::array::from_fn(|cell| cell);
+        let mut ops = Vec::with_capacity(len);
+
+        for &instruction in instructions {
+            let instruction = match instruction {
+                Instruction::Swap(a, b) => {
+                    cells.swap(a, b);
+                    continue;
+                }
+
+                Instruction::Load(val) if cells[0] != 0 => {
+                    ops.push(Op {
+                        run: load_at,
+                        a: cells[0],
+                        b: val as usize,
+                    });
+                    continue;
+                }
+
+                Instruction::Load(val) => Instruction::Load(val),
+                Instruction::XOR(a, b) => Instruction::XOR(cells[a], cells[b]),
+                Instruction::Inc(addr) => Instruction::Inc(cells[addr]),
+                Instruction::Decr(addr) => Instruction::Decr(cells[addr]),
+                Instruction::Add(a, b) => Instruction::Add(cells[a], cells[b]),
+                Instruction::Sub(a, b) => Instruction::Sub(cells[a], cells[b]),
+                Instruction::Put(addr) => Instruction::Put(cells[addr]),
+                Instruction::Jmp(_) => unreachable!("programs with JMP aren't renamed"),
+            };
+
+            ops.push(compile(instruction));
+        }
+
+        let permutation = cells
+            .iter()
+            .enumerate()
+            .any(|(cell, &holder)| cell != holder)
+            .then_some(cells);
+
+        Self {
+            ops,
+            jumps: vec![],
+            permutation,
+            len,
+        }
+    }
+
+    /// Amount of instructions in the program.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Runs the program, starting from the given state.
+    #[inline(always)]
+    pub fn execute(&self, state: &mut State) {
+        if self.jumps.is_empty() {
+            for op in &self.ops {
+                (op.run)(state, op.a, op.b);
+            }
+
+            if let Some(permutation) = &self.permutation {
+                let old = *state;
+
+                for (value, &holder) in state.iter_mut().zip(permutation) {
+                    *value = old[holder];
+                }
+            }
+
+            return;
+        }
+
+        let mut pc = 0;
+
+        while let Some(op) = self.ops.get(pc) {
+            let jump = self.jumps[pc];
+
+            pc += 1;
+
+            // JMP is the only instruction which touches the program counter, so it's handled
+            // here rather than giving every function access to it
+            match jump {
+                Some(ins) => pc = ins,
+                None => (op.run)(state, op.a, op.b),
+            }
+        }
+    }
+
+    /// Runs the program on an all-zeros state, returning the final state.
+    pub fn compute_state(&self) -> State {
+        let mut state = [0; MEM_SIZE];
+
+        self.execute(&mut state);
+
+        state
+    }
+}
+
+impl From<&Program> for CompiledProgram {
+    fn from(program: &Program) -> Self {
+        Self::compile(&program.instructions)
+    }
+}
+
+fn compile(instruction: Instruction) -> Op {
+    let op = |run: fn(&mut State, usize, usize), a, b| Op { run, a, b };
+
+    match instruction {
+        Instruction::Load(val) => op(load, val as usize, 0),
+        Instruction::Swap(a, b) => op(swap, a, b),
+        Instruction::XOR(a, b) => op(xor, a, b),
+        Instruction::Inc(addr) => op(inc, addr, 0),
+        Instruction::Decr(addr) => op(decr, addr, 0),
+        Instruction::Add(a, b) => op(add, a, b),
+        Instruction::Sub(a, b) => op(sub, a, b),
+        Instruction::Put(addr) => op(put, addr, 0),
+        Instruction::Jmp(_) => op(jmp, 0, 0),
+    }
+}
+
+fn load(state: &mut State, val: usize, _: usize) {
+    state[0] = val as MemValue;
+}
+
+/// LOAD, once SWAPs have moved cell 0 elsewhere.
+fn load_at(state: &mut State, addr: usize, val: usize) {
+    state[addr] = val as MemValue;
+}
+
+fn swap(state: &mut State, a: usize, b: usize) {
+    state.swap(a, b);
+}
+
+fn xor(state: &mut State, a: usize, b: usize) {
+    state[a] ^= state[b];
+}
+
+fn inc(state: &mut State, addr: usize, _: usize) {
+    state[addr] = state[addr].wrapping_add(1);
+}
+
+fn decr(state: &mut State, addr: usize, _: usize) {
+    state[addr] = state[addr].wrapping_sub(1);
+}
+
+fn add(state: &mut State, a: usize, b: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+}
+
+fn sub(state: &mut State, a: usize, b: usize) {
+    state[a] = state[a].wrapping_sub(state[b]);
+}
+
+fn put(state: &mut State, addr: usize, _: usize) {
+    println!("{}", state[addr]);
+}
+
+/// Handled by [`CompiledProgram::execute`].
+fn jmp(_: &mut State, _: usize, _: usize) {}
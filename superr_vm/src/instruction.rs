@@ -1,10 +1,18 @@
-use crate::{address::MemoryAddress, vm::MemValue};
+use std::{fmt, str::FromStr};
+
+use anyhow::{bail, Context};
+
+use crate::{
+    address::MemoryAddress,
+    vm::{MemValue, MEM_SIZE},
+};
 
 // This instruction set (along with the assembly format) is heavily based on the one in the below
 // project:
 //
 // https://github.com/AZHenley/superoptimizer
 #[derive(Debug, Clone, PartialEq, PartialOrd, Copy, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Instruction {
     Load(MemValue),
 
@@ -23,9 +31,175 @@ pub enum Instruction {
     Jmp(usize),
 }
 
-impl ToString for Instruction {
-    fn to_string(&self) -> String {
+/// The kind of an instruction, without its operands.
+#[derive(Debug, Clone, PartialEq, PartialOrd, Copy, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Opcode {
+    Load,
+    Swap,
+    XOR,
+    Inc,
+    Decr,
+    Add,
+    Sub,
+    Put,
+    Jmp,
+}
+
+impl Opcode {
+    pub const ALL: [Opcode; 9] = [
+        Opcode::Load,
+        Opcode::Swap,
+        Opcode::XOR,
+        Opcode::Inc,
+        Opcode::Decr,
+        Opcode::Add,
+        Opcode::Sub,
+        Opcode::Put,
+        Opcode::Jmp,
+    ];
+
+    /// The mnemonic used for this opcode in assembly.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Opcode::Load => "LOAD",
+            Opcode::Swap => "SWAP",
+            Opcode::XOR => "XOR",
+            Opcode::Inc => "INC",
+            Opcode::Decr => "DECR",
+            Opcode::Add => "ADD",
+            Opcode::Sub => "SUB",
+            Opcode::Put => "PUT",
+            Opcode::Jmp => "JMP",
+        }
+    }
+
+    /// Looks up an opcode by its mnemonic, ignoring case.
+    pub fn from_name(name: &str) -> Option<Opcode> {
+        Opcode::ALL
+            .into_iter()
+            .find(|opcode| opcode.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Amount of operands instructions with this opcode take.
+    pub fn operands(&self) -> usize {
+        match self {
+            Opcode::Swap | Opcode::XOR | Opcode::Add | Opcode::Sub => 2,
+
+            Opcode::Load | Opcode::Inc | Opcode::Decr | Opcode::Put | Opcode::Jmp => 1,
+        }
+    }
+}
+
+impl Instruction {
+    pub fn opcode(&self) -> Opcode {
+        match self {
+            Instruction::Load(_) => Opcode::Load,
+            Instruction::Swap(_, _) => Opcode::Swap,
+            Instruction::XOR(_, _) => Opcode::XOR,
+            Instruction::Inc(_) => Opcode::Inc,
+            Instruction::Decr(_) => Opcode::Decr,
+            Instruction::Add(_, _) => Opcode::Add,
+            Instruction::Sub(_, _) => Opcode::Sub,
+            Instruction::Put(_) => Opcode::Put,
+            Instruction::Jmp(_) => Opcode::Jmp,
+        }
+    }
+
+    /// Amount of bytes the instruction takes up when encoded, see [`Instruction::encode`].
+    pub fn encoded_len(&self) -> usize {
         match self {
+            Instruction::Jmp(_) => 1 + 4,
+
+            _ => 1 + self.opcode().operands(),
+        }
+    }
+
+    /// Appends the binary encoding of the instruction to `bytes`.
+    ///
+    /// The first byte is the index of the opcode in [`Opcode::ALL`], followed by a byte for
+    /// each operand, except for the target of JMP which is a little endian `u32`.
+    pub fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.push(self.opcode() as u8);
+
+        match *self {
+            Instruction::Load(val) => bytes.push(val),
+
+            Instruction::Swap(a, b)
+            | Instruction::XOR(a, b)
+            | Instruction::Add(a, b)
+            | Instruction::Sub(a, b) => bytes.extend([a as u8, b as u8]),
+
+            Instruction::Inc(addr) | Instruction::Decr(addr) | Instruction::Put(addr) => {
+                bytes.push(addr as u8)
+            }
+
+            Instruction::Jmp(ins) => {
+                let ins = u32::try_from(ins).expect("JMP target doesn't fit in 32 bits");
+
+                bytes.extend(ins.to_le_bytes())
+            }
+        }
+    }
+
+    /// Decodes the instruction at the start of `bytes`, returning it along with the amount of
+    /// bytes it took up.
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<(Instruction, usize)> {
+        let (&byte, operands) = bytes.split_first().context("expected an instruction")?;
+
+        let opcode = *Opcode::ALL
+            .get(byte as usize)
+            .with_context(|| format!("invalid opcode {}", byte))?;
+
+        let operand = |i: usize| {
+            operands
+                .get(i)
+                .copied()
+                .with_context(|| format!("missing operand of {}", opcode.name()))
+        };
+
+        let address = |i: usize| {
+            let addr = operand(i)? as MemoryAddress;
+
+            if addr >= MEM_SIZE {
+                bail!("invalid address {} in {}", addr, opcode.name());
+            }
+
+            Ok(addr)
+        };
+
+        let instruction = match opcode {
+            Opcode::Load => Instruction::Load(operand(0)?),
+
+            Opcode::Swap => Instruction::Swap(address(0)?, address(1)?),
+            Opcode::XOR => Instruction::XOR(address(0)?, address(1)?),
+
+            Opcode::Inc => Instruction::Inc(address(0)?),
+            Opcode::Decr => Instruction::Decr(address(0)?),
+
+            Opcode::Add => Instruction::Add(address(0)?, address(1)?),
+            Opcode::Sub => Instruction::Sub(address(0)?, address(1)?),
+
+            Opcode::Put => Instruction::Put(address(0)?),
+
+            Opcode::Jmp => {
+                let target = operands
+                    .get(..4)
+                    .context("missing operand of JMP")?
+                    .try_into()
+                    .unwrap();
+
+                Instruction::Jmp(u32::from_le_bytes(target) as usize)
+            }
+        };
+
+        Ok((instruction, instruction.encoded_len()))
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
             Instruction::Load(a) => format!("LOAD {}", a),
 
             Instruction::Swap(a, b) => format!("SWAP {} {}", a, b),
@@ -40,14 +214,53 @@ impl ToString for Instruction {
 
             Instruction::Put(a) => format!("PUT {}", a),
             Instruction::Jmp(a) => format!("JMP {}", a),
+        };
+
+        // programs are often printed in aligned columns, so this has to go through `pad` for
+        // width specifiers to apply
+        f.pad(&text)
+    }
+}
+
+impl FromStr for Instruction {
+    type Err = anyhow::Error;
+
+    /// Parses an instruction in the assembly format, as printed by its [`fmt::Display`] impl.
+    /// Anything after a `;` is a comment and is ignored.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let line = s.split(';').next().unwrap().trim();
+
+        let instruction = match parsers::instruction_parser(line) {
+            Ok(("", instruction)) => instruction,
+
+            _ => bail!("invalid instruction: {}", line),
+        };
+
+        let addresses = match instruction {
+            Instruction::Swap(a, b)
+            | Instruction::XOR(a, b)
+            | Instruction::Add(a, b)
+            | Instruction::Sub(a, b) => vec![a, b],
+
+            Instruction::Inc(addr) | Instruction::Decr(addr) | Instruction::Put(addr) => {
+                vec![addr]
+            }
+
+            Instruction::Load(_) | Instruction::Jmp(_) => vec![],
+        };
+
+        if let Some(addr) = addresses.into_iter().find(|&addr| addr >= MEM_SIZE) {
+            bail!("invalid address {} in {}", addr, line);
         }
+
+        Ok(instruction)
     }
 }
 
 mod parsers {
     use nom::{
         bytes::complete::tag,
-        character::complete::{space0, u8},
+        character::complete::{space0, u32, u8},
         sequence::separated_pair,
         Err, IResult,
     };
@@ -88,63 +301,46 @@ mod parsers {
         separated_pair(tag("PUT"), space0, u8)(i)
     }
 
-    fn jmp_parser(i: &str) -> IResult<&str, (&str, u8)> {
-        separated_pair(tag("JMP"), space0, u8)(i)
+    fn jmp_parser(i: &str) -> IResult<&str, (&str, u32)> {
+        separated_pair(tag("JMP"), space0, u32)(i)
     }
 
     pub fn instruction_parser(i: &str) -> IResult<&str, Instruction> {
-        match load_parser(i) {
-            Ok((_, (_, val))) => return Ok((i, Instruction::Load(val as MemValue))),
-            _ => {}
+        if let Ok((rest, (_, val))) = load_parser(i) {
+            return Ok((rest, Instruction::Load(val as MemValue)));
         }
 
-        match swap_parser(i) {
-            Ok((_, (_, (addr1, addr2)))) => {
-                return Ok((i, Instruction::Swap(addr1 as usize, addr2 as usize)))
-            }
-            _ => {}
-        };
+        if let Ok((rest, (_, (addr1, addr2)))) = swap_parser(i) {
+            return Ok((rest, Instruction::Swap(addr1 as usize, addr2 as usize)));
+        }
 
-        match xor_parser(i) {
-            Ok((_, (_, (addr1, addr2)))) => {
-                return Ok((i, Instruction::XOR(addr1 as usize, addr2 as usize)))
-            }
-            _ => {}
-        };
+        if let Ok((rest, (_, (addr1, addr2)))) = xor_parser(i) {
+            return Ok((rest, Instruction::XOR(addr1 as usize, addr2 as usize)));
+        }
 
-        match inc_parser(i) {
-            Ok((_, (_, addr))) => return Ok((i, Instruction::Inc(addr as usize))),
-            _ => {}
-        };
+        if let Ok((rest, (_, addr))) = inc_parser(i) {
+            return Ok((rest, Instruction::Inc(addr as usize)));
+        }
 
-        match decr_parser(i) {
-            Ok((_, (_, addr))) => return Ok((i, Instruction::Decr(addr as usize))),
-            _ => {}
-        };
+        if let Ok((rest, (_, addr))) = decr_parser(i) {
+            return Ok((rest, Instruction::Decr(addr as usize)));
+        }
 
-        match add_parser(i) {
-            Ok((_, (_, (addr1, addr2)))) => {
-                return Ok((i, Instruction::Add(addr1 as usize, addr2 as usize)))
-            }
-            _ => {}
-        };
+        if let Ok((rest, (_, (addr1, addr2)))) = add_parser(i) {
+            return Ok((rest, Instruction::Add(addr1 as usize, addr2 as usize)));
+        }
 
-        match sub_parser(i) {
-            Ok((_, (_, (addr1, addr2)))) => {
-                return Ok((i, Instruction::Sub(addr1 as usize, addr2 as usize)))
-            }
-            _ => {}
-        };
+        if let Ok((rest, (_, (addr1, addr2)))) = sub_parser(i) {
+            return Ok((rest, Instruction::Sub(addr1 as usize, addr2 as usize)));
+        }
 
-        match put_parser(i) {
-            Ok((_, (_, addr))) => return Ok((i, Instruction::Put(addr as usize))),
-            _ => {}
-        };
+        if let Ok((rest, (_, addr))) = put_parser(i) {
+            return Ok((rest, Instruction::Put(addr as usize)));
+        }
 
-        match jmp_parser(i) {
-            Ok((_, (_, ins))) => return Ok((i, Instruction::Jmp(ins as usize))),
-            _ => {}
-        };
+        if let Ok((rest, (_, ins))) = jmp_parser(i) {
+            return Ok((rest, Instruction::Jmp(ins as usize)));
+        }
 
         Err(Err::Failure(nom::error::make_error(
             i,
@@ -155,8 +351,6 @@ mod parsers {
 
 impl From<String> for Instruction {
     fn from(value: String) -> Self {
-        let (_, instruction) = parsers::instruction_parser(&value).expect("invalid instruction");
-
-        instruction
+        value.parse().expect("invalid instruction")
     }
 }
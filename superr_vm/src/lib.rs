@@ -1,4 +1,7 @@
 pub mod address;
+pub mod analysis;
+pub mod batch;
+pub mod compiled;
 pub mod instruction;
 pub mod program;
 pub mod vm;
@@ -1,6 +1,20 @@
-use crate::instruction::Instruction;
+use std::{fmt, str::FromStr};
+
+use anyhow::{bail, Context};
+
+use crate::{instruction::Instruction, vm::VMConfig};
+
+/// Bytes every encoded program starts with.
+pub const MAGIC: [u8; 4] = *b"SBC\0";
+
+/// Version of the encoding, which is bumped whenever it changes.
+pub const ENCODING_VERSION: u8 = 1;
+
+/// Size of the header of an encoded program.
+const HEADER_LEN: usize = MAGIC.len() + 3 + 4;
 
 #[derive(Debug, Clone, Default, PartialEq, PartialOrd, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Program {
     pub instructions: Vec<Instruction>,
 }
@@ -11,4 +25,126 @@ impl Program {
             instructions: vec![],
         }
     }
+
+    /// Encodes the program into the binary format used by `.sbc` files.
+    ///
+    /// The header is made of [`MAGIC`], [`ENCODING_VERSION`], the VM the program was written
+    /// for (its amount of memory cells and the size of its values in bits, one byte each) and
+    /// the amount of instructions as a little endian `u32`. It's followed by the instructions,
+    /// each encoded with [`Instruction::encode`].
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(
+            HEADER_LEN
+                + self
+                    .instructions
+                    .iter()
+                    .map(Instruction::encoded_len)
+                    .sum::<usize>(),
+        );
+
+        let count = u32::try_from(self.instructions.len()).expect("program is too long to encode");
+
+        bytes.extend(MAGIC);
+        bytes.push(ENCODING_VERSION);
+        bytes.push(VMConfig::CURRENT.mem_size as u8);
+        bytes.push(VMConfig::CURRENT.value_bits as u8);
+        bytes.extend(count.to_le_bytes());
+
+        for instruction in &self.instructions {
+            instruction.encode(&mut bytes);
+        }
+
+        bytes
+    }
+
+    /// Decodes a program encoded with [`Program::encode`].
+    ///
+    /// Fails if the program was encoded with another version of the encoding or for a different
+    /// VM, as well as if it's malformed.
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Program> {
+        if !Program::is_encoded(bytes) {
+            bail!("not an encoded program");
+        }
+
+        let header = bytes.get(..HEADER_LEN).context("truncated header")?;
+
+        let version = header[4];
+        let config = VMConfig {
+            mem_size: header[5] as usize,
+            value_bits: header[6] as u32,
+        };
+        let count = u32::from_le_bytes(header[7..11].try_into().unwrap()) as usize;
+
+        if version != ENCODING_VERSION {
+            bail!(
+                "unsupported encoding version {} (expected {})",
+                version,
+                ENCODING_VERSION
+            );
+        }
+
+        if config != VMConfig::CURRENT {
+            bail!(
+                "program was encoded for a VM with {} cells of {} bits (this one has {} cells of {} bits)",
+                config.mem_size,
+                config.value_bits,
+                VMConfig::CURRENT.mem_size,
+                VMConfig::CURRENT.value_bits
+            );
+        }
+
+        let mut rest = &bytes[HEADER_LEN..];
+        let mut instructions = Vec::with_capacity(count.min(rest.len()));
+
+        for i in 0..count {
+            let (instruction, len) =
+                Instruction::decode(rest).with_context(|| format!("instruction {}", i))?;
+
+            instructions.push(instruction);
+            rest = &rest[len..];
+        }
+
+        if !rest.is_empty() {
+            bail!("{} unexpected bytes after the last instruction", rest.len());
+        }
+
+        Ok(Program { instructions })
+    }
+
+    /// Whether the bytes look like an encoded program, rather than assembly.
+    pub fn is_encoded(bytes: &[u8]) -> bool {
+        bytes.starts_with(&MAGIC)
+    }
+}
+
+/// Prints the program as assembly, one instruction per line.
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, instruction) in self.instructions.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+
+            write!(f, "{}", instruction)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Program {
+    type Err = anyhow::Error;
+
+    /// Parses assembly with one instruction per line. Lines which are empty or only hold a
+    /// comment are skipped.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let instructions = s
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.split(';').next().unwrap().trim().is_empty())
+            .map(|(i, line)| line.parse().with_context(|| format!("line {}", i + 1)))
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Program { instructions })
+    }
 }
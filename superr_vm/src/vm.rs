@@ -5,90 +5,93 @@ pub const MEM_SIZE: usize = 12;
 pub type MemValue = u8;
 pub type State = [MemValue; MEM_SIZE];
 
+/// The parameters of the VM, which programs and states are only meaningful for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VMConfig {
+    /// Amount of memory cells.
+    pub mem_size: usize,
+
+    /// Size of the values in the memory cells, in bits.
+    pub value_bits: u32,
+}
+
+impl VMConfig {
+    /// The configuration of this VM.
+    pub const CURRENT: VMConfig = VMConfig {
+        mem_size: MEM_SIZE,
+        value_bits: MemValue::BITS,
+    };
+}
+
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VM {
     pub state: State,
     pub pc: usize,
-    pub program: Program,
 }
 
 impl VM {
     pub fn reset(&mut self) {
         self.state = [0; MEM_SIZE];
         self.pc = 0;
-        self.program = Program::default();
     }
 
+    /// Runs the program, starting from the current state.
     #[inline(always)]
     pub fn execute_program(&mut self, program: Program) {
-        self.program = program;
-
-        while self.pc < self.program.instructions.len() {
-            match self.program.instructions[self.pc] {
-                Instruction::Load(val) => {
-                    self.state[0] = val;
-
-                    self.pc += 1;
-                }
-
-                Instruction::Swap(a, b) => {
-                    self.state.swap(a, b);
-
-                    self.pc += 1;
-                }
-
-                Instruction::XOR(a, b) => {
-                    self.state[a] ^= self.state[b];
-
-                    self.pc += 1;
-                }
+        self.execute(&program.instructions);
+    }
 
-                Instruction::Inc(addr) => {
-                    self.state[addr] = self.state[addr].wrapping_add(1);
+    /// Runs the instructions, starting from the current state. Nothing is allocated, so this
+    /// is what should be used on hot paths.
+    #[inline(always)]
+    pub fn execute(&mut self, instructions: &[Instruction]) {
+        while self.pc < instructions.len() {
+            let instruction = instructions[self.pc];
 
-                    self.pc += 1;
-                }
+            self.pc += 1;
+            self.execute_instruction(instruction);
+        }
 
-                Instruction::Decr(addr) => {
-                    self.state[addr] = self.state[addr].wrapping_sub(1);
+        self.pc = 0;
+    }
 
-                    self.pc += 1;
-                }
+    /// Runs a single instruction. The program counter is expected to already point to the
+    /// next instruction, so that JMP can override it.
+    #[inline(always)]
+    pub fn execute_instruction(&mut self, instruction: Instruction) {
+        match instruction {
+            Instruction::Load(val) => self.state[0] = val,
 
-                Instruction::Add(a, b) => {
-                    self.state[a] = self.state[a].wrapping_add(self.state[b]);
+            Instruction::Swap(a, b) => self.state.swap(a, b),
 
-                    self.pc += 1;
-                }
+            Instruction::XOR(a, b) => self.state[a] ^= self.state[b],
 
-                Instruction::Sub(a, b) => {
-                    self.state[a] = self.state[a].wrapping_sub(self.state[b]);
+            Instruction::Inc(addr) => self.state[addr] = self.state[addr].wrapping_add(1),
 
-                    self.pc += 1;
-                }
+            Instruction::Decr(addr) => self.state[addr] = self.state[addr].wrapping_sub(1),
 
-                Instruction::Put(addr) => {
-                    // TODO: custom writer which may or may not be stdout, so we can handle
-                    // optimization without having to constantly print out
-                    println!("{}", self.state[addr]);
+            Instruction::Add(a, b) => self.state[a] = self.state[a].wrapping_add(self.state[b]),
 
-                    self.pc += 1;
-                }
+            Instruction::Sub(a, b) => self.state[a] = self.state[a].wrapping_sub(self.state[b]),
 
-                Instruction::Jmp(ins) => self.pc = ins,
+            Instruction::Put(addr) => {
+                // TODO: custom writer which may or may not be stdout, so we can handle
+                // optimization without having to constantly print out
+                println!("{}", self.state[addr]);
             }
-        }
 
-        self.program = Program::default();
-        self.pc = 0;
+            Instruction::Jmp(ins) => self.pc = ins,
+        }
     }
 
     #[inline(always)]
     pub fn compute_state(program: &Program) -> State {
         let mut vm = VM::default();
 
-        vm.execute_program(program.clone());
+        vm.execute(&program.instructions);
 
-        return vm.state;
+        vm.state
     }
 }